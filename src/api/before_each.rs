@@ -0,0 +1,43 @@
+use super::VmState;
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyTuple},
+};
+
+/// Decorator registering a hook that runs before every definition.
+///
+/// Like definitions, the hook receives the client and fixtures it asks for by parameter name.
+#[pyclass(name = "before_each")]
+pub struct PyBeforeEach {
+    wraps: Py<PyAny>,
+}
+
+#[pymethods]
+impl PyBeforeEach {
+    #[new]
+    fn __new__(py: Python<'_>, wraps: Py<PyAny>) -> PyResult<Self> {
+        VmState::current(py)?
+            .before_each
+            .lock()
+            .unwrap()
+            .push(wraps.clone_ref(py));
+
+        Ok(PyBeforeEach { wraps })
+    }
+
+    #[pyo3(signature = (*args, **kwargs))]
+    fn __call__(
+        &self,
+        py: Python<'_>,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<PyAny>> {
+        self.wraps.call(py, args, kwargs)
+    }
+}
+
+pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyBeforeEach>()?;
+
+    Ok(())
+}
@@ -102,7 +102,7 @@ impl PyAsyncResponses {
                     0
                 };
             });
-        responses_by_time.sort_by(|a, b| a.0.cmp(&b.0));
+        responses_by_time.sort_by_key(|a| a.0);
 
         // (code, status, amount, histogram_chars)
         let mut status_codes: Vec<(u16, String, u64, u64)> = Vec::new();
@@ -123,7 +123,7 @@ impl PyAsyncResponses {
                 0
             };
         }
-        status_codes.sort_by(|a, b| a.0.cmp(&b.0));
+        status_codes.sort_by_key(|a| a.0);
 
         println!("Total Responses: {}", total_responses);
         println!("Total: {} ms", self.responses_stats.total_duration);
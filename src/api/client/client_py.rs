@@ -1,4 +1,8 @@
-use super::{PyAsyncResponses, PyMethod, PyRequest, PyResponse, async_responses::ResponseStats};
+use super::{
+    PrintCurl, PyAsyncResponses, PyMethod, PyRequest, PyResponse, async_responses::ResponseStats,
+    request::ClientOptions, to_curl,
+};
+use crate::api::{LogOutput, VmState, check_response, dry_run_response};
 use indicatif::{ProgressBar, ProgressStyle};
 use pyo3::{prelude::*, types::PyDict};
 use reqwest::Client;
@@ -9,17 +13,124 @@ use tokio::{
 };
 
 #[pyclass(name = "Client")]
-pub struct PyClient {}
+pub struct PyClient {
+    /// Runtime driving sync requests, pooled connections are bound to it.
+    runtime: tokio::runtime::Runtime,
+
+    /// Long-lived clients, one per distinct set of client options.
+    clients: HashMap<ClientOptions, Client>,
+
+    /// Timeout in milliseconds for requests that do not pass `timeout`.
+    default_timeout: Option<u64>,
+
+    /// State of the VM the client records its requests in.
+    state: Arc<VmState>,
+}
 
 impl PyClient {
-    fn send_request(&self, request: PyRequest) -> PyResult<PyResponse> {
-        let request_builder = request.to_reqwest_blocking();
+    pub fn new(state: Arc<VmState>, default_timeout: Option<u64>) -> PyResult<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| {
+                PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("Runtime error: {}", e))
+            })?;
+
+        Ok(PyClient {
+            runtime,
+            clients: HashMap::new(),
+            default_timeout,
+            state,
+        })
+    }
+
+    /// Builds a request from keyword arguments, applying the client's default timeout.
+    fn request(
+        &self,
+        url: String,
+        method: PyMethod,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<PyRequest> {
+        let mut request = PyRequest::from_args(url, method, kwargs)?;
+
+        let has_timeout = kwargs.is_some_and(|d| d.contains("timeout").unwrap_or(false));
+        if let (Some(timeout), false) = (self.default_timeout, has_timeout) {
+            request.timeout = timeout;
+        }
+
+        Ok(request)
+    }
+
+    /// Returns the pooled client for the request, or a new one for a fresh connection.
+    fn client_for(&mut self, request: &PyRequest) -> PyResult<Client> {
+        let options = request.client_options();
+
+        if request.fresh_connection {
+            return build_client(&options, 0);
+        }
+
+        if let Some(client) = self.clients.get(&options) {
+            return Ok(client.clone());
+        }
+
+        let client = build_client(&options, 1_000_000)?;
+        self.clients.insert(options, client.clone());
+        Ok(client)
+    }
+
+    /// Prints the request as a curl command if the VM was asked to, or if it is not sent.
+    fn print_curl(&self, request: &PyRequest, dry_run: bool) {
+        let redact = match (*self.state.print_curl.lock().unwrap(), dry_run) {
+            (PrintCurl::Off, false) => return,
+            (PrintCurl::Redacted, _) => true,
+            _ => false,
+        };
+
+        let curl = to_curl(request, redact);
+        let output = self.state.logger_config.lock().unwrap().output;
+        match dry_run {
+            true => output.print(&format!("Dry run, not sent:\n{}", curl)),
+            false => output.print(&curl),
+        }
+    }
+
+    fn send_request(&mut self, request: PyRequest) -> PyResult<PyResponse> {
+        let dry_run = *self.state.dry_run.lock().unwrap();
+        self.print_curl(&request, dry_run);
+
+        let started = chrono::Utc::now();
+        let response = match dry_run {
+            true => Python::with_gil(|py| dry_run_response(py, &self.state, &request, started)),
+            false => self.execute(&request, started),
+        };
+
+        let record = request.to_record(started, response.as_ref().map_err(|e| e.to_string()));
+        self.state.requests.lock().unwrap().push(record);
+
+        // Stubbed responses are not the server's, there is nothing to check
+        if let (Ok(response), false) = (&response, dry_run) {
+            check_response(&self.state, &request, response);
+        }
+
+        response
+    }
+
+    fn execute(
+        &mut self,
+        request: &PyRequest,
+        started: chrono::DateTime<chrono::Utc>,
+    ) -> PyResult<PyResponse> {
+        let request_builder = request.to_reqwest(&self.client_for(request)?);
 
         let start = std::time::Instant::now();
-        let response = request_builder.send().map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("Request failed: {}", e))
-        })?;
-        let duration = start.elapsed();
+        let (response, duration) = self
+            .runtime
+            .block_on(async {
+                let response = request_builder.send().await?;
+                let duration = start.elapsed();
+                Ok((response, duration))
+            })
+            .map_err(|e| request_error(request, "Request failed", e, start.elapsed()))?;
 
         let status_code = response.status().as_u16();
         let status = response.status().to_string();
@@ -30,11 +141,8 @@ impl PyClient {
             .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or("").to_string()))
             .collect();
 
-        let response_body = response.text().map_err(|e| {
-            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
-                "Failed to read response body: {}",
-                e
-            ))
+        let response_body = self.runtime.block_on(response.text()).map_err(|e| {
+            request_error(request, "Failed to read response body", e, start.elapsed())
         })?;
 
         Ok(PyResponse {
@@ -43,6 +151,9 @@ impl PyClient {
             headers,
             body: Some(response_body),
             duration: duration.as_millis() as u64,
+            request: Some(request.clone()),
+            started,
+            error: None,
         })
     }
 
@@ -52,7 +163,22 @@ impl PyClient {
         amount: u32,
         threads: u32,
     ) -> PyResult<PyAsyncResponses> {
-        let progress_bar = ProgressBar::new(amount.into());
+        let dry_run = *self.state.dry_run.lock().unwrap();
+        self.print_curl(&request, dry_run);
+        if dry_run {
+            let responses = Python::with_gil(|py| {
+                (0..amount)
+                    .map(|_| dry_run_response(py, &self.state, &request, chrono::Utc::now()))
+                    .collect::<PyResult<Vec<PyResponse>>>()
+            })?;
+            return Ok(self.async_responses(&request, responses, 0));
+        }
+
+        let output = self.state.logger_config.lock().unwrap().output;
+        let progress_bar = match output {
+            LogOutput::Silent => ProgressBar::hidden(),
+            _ => ProgressBar::new(amount.into()),
+        };
         let style = ProgressStyle::with_template(
             "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>5}/{len:5} {msg}",
         )
@@ -71,10 +197,16 @@ impl PyClient {
 
         let mut total_duration = 0;
 
+        let options = request.client_options();
+        let client = build_client(&options, 1_000_000)?;
+
         let results = rt.block_on(async {
+            let fresh_connection = request.fresh_connection;
+            let request_info = Arc::new(request.clone());
+
             let request_template = Arc::new(
                 request
-                    .to_reqwest()
+                    .to_reqwest(&client)
                     .build()
                     .expect("Failed to build request"),
             );
@@ -91,14 +223,22 @@ impl PyClient {
                 let semaphore = semaphore.clone();
                 let request = request_template.clone();
                 let progress_bar = progress_bar.clone();
+                let client = client.clone();
+                let request_info = request_info.clone();
 
                 let handle = task::spawn(async move {
-                    let client = Client::new();
-
                     for _ in 0..per_thread {
                         let _permit = semaphore.acquire().await.unwrap();
                         let req = request.try_clone().expect("Failed to clone request");
+                        let client = if fresh_connection {
+                            options
+                                .build_client(0)
+                                .expect("Failed to build reqwest client")
+                        } else {
+                            client.clone()
+                        };
 
+                        let started = chrono::Utc::now();
                         let start = std::time::Instant::now();
 
                         match client.execute(req).await {
@@ -122,6 +262,9 @@ impl PyClient {
                                     headers,
                                     body,
                                     duration: duration.as_millis() as u64,
+                                    request: Some((*request_info).clone()),
+                                    started,
+                                    error: None,
                                 };
 
                                 if let Err(e) = tx.send(response) {
@@ -132,12 +275,23 @@ impl PyClient {
                                 let status = e
                                     .status()
                                     .unwrap_or(reqwest::StatusCode::INTERNAL_SERVER_ERROR);
+                                let status_text = request_info
+                                    .describe_timeout(&e, start.elapsed())
+                                    .unwrap_or_else(|| status.to_string());
                                 if let Err(e) = tx.send(PyResponse {
                                     status_code: status.as_u16(),
-                                    status: status.to_string(),
+                                    status: status_text,
                                     headers: HashMap::new(),
                                     body: None,
                                     duration: start.elapsed().as_millis() as u64,
+                                    request: Some((*request_info).clone()),
+                                    started,
+                                    error: Some(error_message(
+                                        &request_info,
+                                        "Request failed",
+                                        &e,
+                                        start.elapsed(),
+                                    )),
                                 }) {
                                     eprintln!("Failed to send error response: {}", e);
                                 }
@@ -169,12 +323,41 @@ impl PyClient {
             responses
         });
 
-        println!("[{}] Responses received", results.len());
+        // Requests that failed have no response to check
+        for response in results.iter().filter(|r| r.error.is_none()) {
+            check_response(&self.state, &request, response);
+        }
+
+        Ok(self.async_responses(&request, results, total_duration))
+    }
+
+    /// Records the responses of async requests and collects their stats.
+    fn async_responses(
+        &self,
+        request: &PyRequest,
+        results: Vec<PyResponse>,
+        total_duration: u64,
+    ) -> PyAsyncResponses {
+        let output = self.state.logger_config.lock().unwrap().output;
+        output.print(&format!("[{}] Responses received", results.len()));
+
+        self.state
+            .requests
+            .lock()
+            .unwrap()
+            .extend(results.iter().map(|r| {
+                let outcome = match &r.error {
+                    Some(error) => Err(error.clone()),
+                    None => Ok(r),
+                };
+                request.to_record(r.started, outcome)
+            }));
 
         let durations: Vec<u64> = results.iter().map(|r| r.duration).collect();
         let response_codes: Vec<u16> = results.iter().map(|r| r.status_code).collect();
-        let async_responses = PyAsyncResponses {
-            responses: results.clone(),
+
+        PyAsyncResponses {
+            responses: results,
             responses_stats: {
                 ResponseStats {
                     durations,
@@ -182,17 +365,16 @@ impl PyClient {
                     total_duration,
                 }
             },
-        };
-
-        Ok(async_responses)
+        }
     }
 }
 
 #[pymethods]
 impl PyClient {
     #[new]
-    fn __new__() -> Self {
-        PyClient {}
+    #[pyo3(signature = (timeout = None))]
+    fn __new__(py: Python<'_>, timeout: Option<u64>) -> PyResult<Self> {
+        PyClient::new(VmState::current(py)?, timeout)
     }
 
     #[pyo3(signature = (url, **kwargs))]
@@ -202,7 +384,7 @@ impl PyClient {
             .and_then(|m| m.extract::<PyMethod>().ok())
             .unwrap_or(PyMethod::Get);
 
-        let request = PyRequest::from_args(url, method, kwargs)?;
+        let request = self.request(url, method, kwargs)?;
         self.send_request(request)
     }
 
@@ -227,13 +409,13 @@ impl PyClient {
             .and_then(|v| v.extract::<u32>().ok())
             .unwrap_or(1);
 
-        let request = PyRequest::from_args(url, method, kwargs)?;
+        let request = self.request(url, method, kwargs)?;
         self.send_requests_async(request, amount, threads)
     }
 
     #[pyo3(signature = (url, **kwargs))]
     fn get(&mut self, url: String, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<PyResponse> {
-        let request = PyRequest::from_args(url, PyMethod::Get, kwargs)?;
+        let request = self.request(url, PyMethod::Get, kwargs)?;
         self.send_request(request)
     }
 
@@ -243,7 +425,7 @@ impl PyClient {
         url: String,
         kwargs: Option<&Bound<'_, PyDict>>,
     ) -> PyResult<PyAsyncResponses> {
-        let request = PyRequest::from_args(url, PyMethod::Get, kwargs)?;
+        let request = self.request(url, PyMethod::Get, kwargs)?;
 
         let amount = kwargs
             .and_then(|d| d.get_item("amount").ok()?)
@@ -260,7 +442,7 @@ impl PyClient {
 
     #[pyo3(signature = (url, **kwargs))]
     fn post(&mut self, url: String, kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<PyResponse> {
-        let request = PyRequest::from_args(url, PyMethod::Post, kwargs)?;
+        let request = self.request(url, PyMethod::Post, kwargs)?;
         self.send_request(request)
     }
 
@@ -270,7 +452,7 @@ impl PyClient {
         url: String,
         kwargs: Option<&Bound<'_, PyDict>>,
     ) -> PyResult<PyAsyncResponses> {
-        let request = PyRequest::from_args(url, PyMethod::Post, kwargs)?;
+        let request = self.request(url, PyMethod::Post, kwargs)?;
 
         let amount = kwargs
             .and_then(|d| d.get_item("amount").ok()?)
@@ -285,6 +467,51 @@ impl PyClient {
     }
 }
 
+fn build_client(options: &ClientOptions, max_idle: usize) -> PyResult<Client> {
+    options.build_client(max_idle).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!("Failed to build client: {}", e))
+    })
+}
+
+/// Converts a reqwest error into a Python exception, naming the timeout that ran out.
+fn request_error(
+    request: &PyRequest,
+    context: &str,
+    error: reqwest::Error,
+    elapsed: std::time::Duration,
+) -> PyErr {
+    let message = error_message(request, context, &error, elapsed);
+    match error.is_timeout() {
+        true => PyErr::new::<pyo3::exceptions::PyTimeoutError, _>(message),
+        false => PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(message),
+    }
+}
+
+/// Describes why a request failed: the timeout that ran out, or the error and its causes.
+fn error_message(
+    request: &PyRequest,
+    context: &str,
+    error: &reqwest::Error,
+    elapsed: std::time::Duration,
+) -> String {
+    match request.describe_timeout(error, elapsed) {
+        Some(timeout) => format!(
+            "{}: {} {} {}",
+            context, request.method, request.url, timeout
+        ),
+        None => {
+            let mut message = format!("{}: {}", context, error);
+            let mut source = std::error::Error::source(error);
+            while let Some(cause) = source {
+                message.push_str(&format!(": {}", cause));
+                source = cause.source();
+            }
+
+            message
+        }
+    }
+}
+
 pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
     module.add_class::<PyClient>()?;
 
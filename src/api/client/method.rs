@@ -11,6 +11,38 @@ pub enum PyMethod {
     Patch,
 }
 
+impl std::fmt::Display for PyMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let method = match self {
+            PyMethod::Get => "GET",
+            PyMethod::Head => "HEAD",
+            PyMethod::Post => "POST",
+            PyMethod::Put => "PUT",
+            PyMethod::Delete => "DELETE",
+            PyMethod::Patch => "PATCH",
+        };
+
+        write!(f, "{}", method)
+    }
+}
+
+impl std::str::FromStr for PyMethod {
+    type Err = anyhow::Error;
+
+    /// Parses a method name, in any case.
+    fn from_str(method: &str) -> Result<Self, Self::Err> {
+        match method.to_uppercase().as_str() {
+            "GET" => Ok(PyMethod::Get),
+            "HEAD" => Ok(PyMethod::Head),
+            "POST" => Ok(PyMethod::Post),
+            "PUT" => Ok(PyMethod::Put),
+            "DELETE" => Ok(PyMethod::Delete),
+            "PATCH" => Ok(PyMethod::Patch),
+            _ => Err(anyhow::anyhow!("Unsupported method: {}", method)),
+        }
+    }
+}
+
 pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
     module.add_class::<PyMethod>()?;
     module.add("GET", PyMethod::Get)?;
@@ -1,11 +1,13 @@
 mod async_responses;
 mod client_py;
+mod curl;
 mod method;
 mod request;
 mod response;
 
 pub use async_responses::PyAsyncResponses;
 pub use client_py::PyClient;
+pub use curl::{PrintCurl, to_curl};
 pub use method::PyMethod;
 pub use request::PyRequest;
 pub use response::PyResponse;
@@ -16,6 +18,7 @@ pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
     async_responses::register(module)?;
     client_py::register(module)?;
     method::register(module)?;
+    module.add_class::<PyRequest>()?;
     response::register(module)?;
 
     Ok(())
@@ -1,16 +1,56 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
-use super::PyMethod;
+use super::{PyMethod, PyResponse, curl};
+use crate::report::RequestRecord;
 use pyo3::{prelude::*, types::PyDict};
 
+/// Settings that reqwest only supports per client, not per request.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct ClientOptions {
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    /// Accept invalid certificates, like `curl -k`
+    pub insecure: bool,
+}
+
+impl ClientOptions {
+    /// Builds a client keeping up to `max_idle` idle connections per host.
+    pub fn build_client(&self, max_idle: usize) -> reqwest::Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder().pool_max_idle_per_host(max_idle);
+
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(Duration::from_millis(connect_timeout));
+        }
+        if let Some(read_timeout) = self.read_timeout {
+            builder = builder.read_timeout(Duration::from_millis(read_timeout));
+        }
+        if self.insecure {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        builder.build()
+    }
+}
+
+/// A request as sent by the client, available as `response.request`.
+#[pyclass(name = "Request")]
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct PyRequest {
+    #[pyo3(get)]
     pub url: String,
+    #[pyo3(get)]
     pub method: PyMethod,
+    #[pyo3(get)]
     pub headers: HashMap<String, String>,
+    #[pyo3(get)]
     pub params: HashMap<String, String>,
     pub body: Option<Vec<u8>>,
+    #[pyo3(get)]
     pub timeout: u64,
+    pub connect_timeout: Option<u64>,
+    pub read_timeout: Option<u64>,
+    pub fresh_connection: bool,
+    pub insecure: bool,
 }
 
 impl PyRequest {
@@ -73,6 +113,24 @@ impl PyRequest {
             .and_then(|v| v.extract::<u64>().ok())
             .unwrap_or(100_000); // Default timeout of 100 seconds
 
+        let connect_timeout = kwargs
+            .and_then(|d| d.get_item("connect_timeout").ok()?)
+            .and_then(|v| v.extract::<u64>().ok());
+
+        let read_timeout = kwargs
+            .and_then(|d| d.get_item("read_timeout").ok()?)
+            .and_then(|v| v.extract::<u64>().ok());
+
+        let fresh_connection = kwargs
+            .and_then(|d| d.get_item("fresh_connection").ok()?)
+            .and_then(|v| v.extract::<bool>().ok())
+            .unwrap_or(false);
+
+        let insecure = kwargs
+            .and_then(|d| d.get_item("insecure").ok()?)
+            .and_then(|v| v.extract::<bool>().ok())
+            .unwrap_or(false);
+
         Ok(PyRequest {
             url,
             method,
@@ -80,46 +138,69 @@ impl PyRequest {
             params,
             body,
             timeout,
+            connect_timeout,
+            read_timeout,
+            fresh_connection,
+            insecure,
         })
     }
 
-    #[allow(dead_code)]
-    pub fn to_reqwest(&self) -> reqwest::RequestBuilder {
-        let client = reqwest::Client::builder()
-            .pool_max_idle_per_host(1_000_000)
-            .build()
-            .expect("Failed to build reqwest client");
+    pub fn client_options(&self) -> ClientOptions {
+        ClientOptions {
+            connect_timeout: self.connect_timeout,
+            read_timeout: self.read_timeout,
+            insecure: self.insecure,
+        }
+    }
 
-        let mut request_builder = match self.method {
-            PyMethod::Get => client.get(&self.url),
-            PyMethod::Head => client.head(&self.url),
-            PyMethod::Post => client.post(&self.url),
-            PyMethod::Put => client.put(&self.url),
-            PyMethod::Delete => client.delete(&self.url),
-            PyMethod::Patch => client.patch(&self.url),
+    /// Creates a report record of this request and its outcome.
+    pub fn to_record(
+        &self,
+        started_at: chrono::DateTime<chrono::Utc>,
+        outcome: Result<&PyResponse, String>,
+    ) -> RequestRecord {
+        let (response, error) = match outcome {
+            Ok(response) => (Some(response.to_record()), None),
+            Err(error) => (None, Some(error)),
         };
 
-        for (key, value) in &self.headers {
-            request_builder = request_builder.header(key, value);
+        RequestRecord {
+            started_at,
+            method: self.method.to_string(),
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            params: self.params.clone(),
+            body: self
+                .body
+                .as_ref()
+                .map(|b| String::from_utf8_lossy(b).into_owned()),
+            response,
+            error,
         }
+    }
 
-        for (key, value) in &self.params {
-            request_builder = request_builder.query(&[(key.as_str(), value.as_str())]);
+    /// Describes which timeout ran out, if `error` is a timeout.
+    pub fn describe_timeout(&self, error: &reqwest::Error, elapsed: Duration) -> Option<String> {
+        if !error.is_timeout() {
+            return None;
         }
 
-        if let Some(body) = &self.body {
-            request_builder = request_builder.body(body.clone());
-        }
+        let (phase, limit) = match (self.connect_timeout, self.read_timeout) {
+            (Some(connect), _) if error.is_connect() => ("connect", connect),
+            (_, Some(read)) if elapsed.as_millis() < self.timeout as u128 => ("read", read),
+            _ => ("total", self.timeout),
+        };
 
-        request_builder.timeout(std::time::Duration::from_millis(self.timeout))
+        Some(format!(
+            "{} timeout of {}ms exceeded after {}ms",
+            phase,
+            limit,
+            elapsed.as_millis()
+        ))
     }
 
-    pub fn to_reqwest_blocking(&self) -> reqwest::blocking::RequestBuilder {
-        let client = reqwest::blocking::Client::builder()
-            .pool_max_idle_per_host(1_000_000)
-            .build()
-            .expect("Failed to build reqwest client");
-
+    /// Builds a request on the given client.
+    pub fn to_reqwest(&self, client: &reqwest::Client) -> reqwest::RequestBuilder {
         let mut request_builder = match self.method {
             PyMethod::Get => client.get(&self.url),
             PyMethod::Head => client.head(&self.url),
@@ -141,8 +222,22 @@ impl PyRequest {
             request_builder = request_builder.body(body.clone());
         }
 
-        // request_builder.timeout(std::time::Duration::from_millis(self.timeout))
+        request_builder.timeout(Duration::from_millis(self.timeout))
+    }
+}
+
+#[pymethods]
+impl PyRequest {
+    #[getter]
+    fn body(&self) -> Option<String> {
+        self.body
+            .as_ref()
+            .map(|b| String::from_utf8_lossy(b).into_owned())
+    }
 
-        request_builder
+    /// Returns an equivalent curl command, with secrets replaced if `redact` is set.
+    #[pyo3(signature = (redact = false))]
+    fn to_curl(&self, redact: bool) -> String {
+        curl::to_curl(self, redact)
     }
 }
@@ -1,3 +1,5 @@
+use super::PyRequest;
+use crate::report::ResponseRecord;
 use pyo3::prelude::*;
 use std::collections::HashMap;
 
@@ -18,6 +20,28 @@ pub struct PyResponse {
 
     #[pyo3(get)]
     pub duration: u64,
+
+    /// The request this is the response to
+    #[pyo3(get)]
+    pub request: Option<PyRequest>,
+
+    /// When the request was sent
+    pub started: chrono::DateTime<chrono::Utc>,
+
+    /// Why an async request got no response, its status is a placeholder then
+    pub error: Option<String>,
+}
+
+impl PyResponse {
+    pub fn to_record(&self) -> ResponseRecord {
+        ResponseRecord {
+            status_code: self.status_code,
+            status: self.status.clone(),
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            duration_ms: self.duration,
+        }
+    }
 }
 
 #[pymethods]
@@ -0,0 +1,56 @@
+use super::VmState;
+use pyo3::{prelude::*, wrap_pyfunction};
+use std::path::PathBuf;
+
+/// Loads test cases from a CSV or JSON file, for `@nc.define(params=...)`.
+///
+/// CSV rows become dicts keyed by the header row, JSON files have to contain a list. Relative
+/// paths are relative to the script.
+#[pyfunction]
+fn data(py: Python<'_>, path: PathBuf) -> PyResult<Py<PyAny>> {
+    let path = VmState::current(py)?.script_path(&path);
+    let content = std::fs::read_to_string(&path).map_err(|e| {
+        PyErr::new::<pyo3::exceptions::PyIOError, _>(format!(
+            "Failed to read data file {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let cases = match extension.as_deref() {
+        Some("csv") => {
+            let reader = py.import("csv")?.call_method1(
+                "DictReader",
+                (py.import("io")?.call_method1("StringIO", (content,))?,),
+            )?;
+            py.import("builtins")?.call_method1("list", (reader,))?
+        }
+        Some("json") => {
+            let cases = py.import("json")?.call_method1("loads", (content,))?;
+            if !cases.is_instance_of::<pyo3::types::PyList>() {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Data file {} has to contain a list of cases",
+                    path.display()
+                )));
+            }
+            cases
+        }
+        _ => {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Unsupported data file {}, expected .csv or .json",
+                path.display()
+            )));
+        }
+    };
+
+    Ok(cases.unbind())
+}
+
+pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(data, module)?)?;
+
+    Ok(())
+}
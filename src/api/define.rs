@@ -1,17 +1,245 @@
-use super::REGISTRY;
-use pyo3::prelude::*;
+use super::VmState;
+use pyo3::{
+    prelude::*,
+    types::{PyBool, PyDict, PyFloat, PyInt, PyString, PyTuple},
+};
 
+/// A function registered with `@nc.define`.
+#[derive(Debug)]
+pub struct Definition {
+    pub func: Py<PyAny>,
+    /// Case passed as the `case` argument of a parametrized definition
+    pub case: Option<Py<PyAny>>,
+    pub meta: DefinitionMeta,
+}
+
+/// Everything known about a definition besides the function itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DefinitionMeta {
+    /// Name of the definition, `name[case-id]` for cases of a parametrized definition
+    pub name: String,
+    /// Id of the case, if the definition is parametrized
+    pub case: Option<String>,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    /// Definitions that have to pass before this one runs
+    pub depends_on: Vec<String>,
+    /// Timeout in milliseconds, also the default timeout of requests sent by the definition.
+    /// Checked once the definition returns, a running definition is not interrupted
+    pub timeout: Option<u64>,
+    /// Reason to skip the definition, empty if none was given
+    pub skip: Option<String>,
+    pub doc: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+impl DefinitionMeta {
+    /// Name of the definition without the case id.
+    pub fn base_name(&self) -> &str {
+        match &self.case {
+            Some(case) => self
+                .name
+                .strip_suffix(&format!("[{}]", case))
+                .unwrap_or(&self.name),
+            None => &self.name,
+        }
+    }
+}
+
+/// Options passed to `@nc.define(...)`.
+#[derive(Debug, Default)]
+struct DefineOptions {
+    tags: Vec<String>,
+    description: Option<String>,
+    depends_on: Vec<String>,
+    timeout: Option<u64>,
+    skip: Option<String>,
+    /// Cases and their ids, the definition is registered once per case
+    params: Option<Vec<(String, Py<PyAny>)>>,
+}
+
+/// Decorator registering a definition.
+///
+/// Used either bare, `@nc.define`, or with options, `@nc.define(tags=["smoke"], skip="flaky")`.
 #[pyclass(name = "define")]
-pub struct PyDefine {}
+pub struct PyDefine {
+    wraps: Option<Py<PyAny>>,
+    options: DefineOptions,
+}
+
+impl PyDefine {
+    fn register(py: Python<'_>, wraps: Py<PyAny>, options: &DefineOptions) -> PyResult<()> {
+        let func = wraps.bind(py);
+        let name = func.getattr("__name__")?.extract::<String>()?;
+
+        let doc = py
+            .import("inspect")?
+            .call_method1("getdoc", (func,))?
+            .extract::<Option<String>>()?;
+        let (file, line) = match func.getattr("__code__") {
+            Ok(code) => (
+                code.getattr("co_filename")?.extract::<Option<String>>()?,
+                code.getattr("co_firstlineno")?.extract::<Option<u32>>()?,
+            ),
+            Err(_) => (None, None),
+        };
+
+        let state = VmState::current(py)?;
+        // Dependencies without a namespace are in the same file
+        let (name, depends_on) = match state.namespace.lock().unwrap().as_deref() {
+            Some(namespace) => (
+                format!("{}::{}", namespace, name),
+                options
+                    .depends_on
+                    .iter()
+                    .map(|dep| match dep.contains("::") {
+                        true => dep.clone(),
+                        false => format!("{}::{}", namespace, dep),
+                    })
+                    .collect(),
+            ),
+            None => (name, options.depends_on.clone()),
+        };
+
+        let meta = DefinitionMeta {
+            name,
+            case: None,
+            tags: options.tags.clone(),
+            description: options.description.clone(),
+            depends_on,
+            timeout: options.timeout,
+            skip: options.skip.clone(),
+            doc,
+            file,
+            line,
+        };
+
+        let mut registry = state.registry.lock().unwrap();
+        match &options.params {
+            Some(params) => {
+                for (id, case) in params {
+                    registry.push(Definition {
+                        func: wraps.clone_ref(py),
+                        case: Some(case.clone_ref(py)),
+                        meta: DefinitionMeta {
+                            name: format!("{}[{}]", meta.name, id),
+                            case: Some(id.clone()),
+                            ..meta.clone()
+                        },
+                    });
+                }
+            }
+            None => registry.push(Definition {
+                func: wraps,
+                case: None,
+                meta,
+            }),
+        }
+
+        Ok(())
+    }
+}
+
+/// Collects the cases of a parametrized definition and gives each one an id.
+///
+/// The id is the `id` key of dict cases, the value itself for strings, numbers and bools, and
+/// the index of the case otherwise.
+fn collect_cases(params: &Bound<'_, PyAny>) -> PyResult<Vec<(String, Py<PyAny>)>> {
+    let mut cases: Vec<(String, Py<PyAny>)> = Vec::new();
+
+    for (i, case) in params.try_iter()?.enumerate() {
+        let case = case?;
+        let id = match case.downcast::<PyDict>() {
+            Ok(dict) => match dict.get_item("id")? {
+                Some(id) => id.str()?.to_string(),
+                None => i.to_string(),
+            },
+            Err(_)
+                if case.is_instance_of::<PyString>()
+                    || case.is_instance_of::<PyInt>()
+                    || case.is_instance_of::<PyFloat>() =>
+            {
+                case.str()?.to_string()
+            }
+            Err(_) => i.to_string(),
+        };
+
+        if cases.iter().any(|(other, _)| *other == id) {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Duplicate case id `{}`",
+                id
+            )));
+        }
+        cases.push((id, case.unbind()));
+    }
+
+    Ok(cases)
+}
 
 #[pymethods]
 impl PyDefine {
     #[new]
-    fn __new__(wraps: Py<PyAny>) -> Self {
-        let mut registry = REGISTRY.lock().unwrap();
-        registry.push(wraps);
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (wraps = None, *, tags = None, description = None, depends_on = None, timeout = None, skip = None, params = None))]
+    fn __new__(
+        py: Python<'_>,
+        wraps: Option<Py<PyAny>>,
+        tags: Option<Vec<String>>,
+        description: Option<String>,
+        depends_on: Option<Vec<String>>,
+        timeout: Option<u64>,
+        skip: Option<&Bound<'_, PyAny>>,
+        params: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<Self> {
+        let skip = match skip {
+            None => None,
+            Some(skip) if skip.is_instance_of::<PyBool>() => {
+                skip.extract::<bool>()?.then(String::new)
+            }
+            Some(skip) => Some(skip.extract::<String>().map_err(|_| {
+                PyErr::new::<pyo3::exceptions::PyTypeError, _>(
+                    "skip must be a bool or a string with the reason",
+                )
+            })?),
+        };
+
+        let options = DefineOptions {
+            tags: tags.unwrap_or_default(),
+            description,
+            depends_on: depends_on.unwrap_or_default(),
+            timeout,
+            skip,
+            params: params.map(collect_cases).transpose()?,
+        };
+
+        if let Some(wraps) = &wraps {
+            PyDefine::register(py, wraps.clone_ref(py), &options)?;
+        }
+
+        Ok(PyDefine { wraps, options })
+    }
+
+    /// Calls the wrapped function, or registers `args[0]` when used as `@nc.define(...)`.
+    #[pyo3(signature = (*args, **kwargs))]
+    fn __call__(
+        &self,
+        py: Python<'_>,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<PyAny>> {
+        if let Some(wraps) = &self.wraps {
+            return wraps.call(py, args, kwargs);
+        }
+
+        let wraps = args.get_item(0)?.unbind();
+        PyDefine::register(py, wraps.clone_ref(py), &self.options)?;
+        let define = PyDefine {
+            wraps: Some(wraps),
+            options: DefineOptions::default(),
+        };
 
-        PyDefine {}
+        Ok(Py::new(py, define)?.into_any())
     }
 }
 
@@ -1,7 +1,12 @@
+use super::VmState;
 use pyo3::{prelude::*, wrap_pyfunction};
 
 #[pyfunction]
-fn env(var: String) -> PyResult<Option<String>> {
+fn env(py: Python<'_>, var: String) -> PyResult<Option<String>> {
+    if let Some(value) = VmState::current(py)?.env.lock().unwrap().get(&var) {
+        return Ok(Some(value.clone()));
+    }
+
     if dotenv::dotenv().is_ok() {
         Ok(dotenv::var(var).ok())
     } else {
@@ -0,0 +1,302 @@
+use super::VmState;
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyTuple},
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// How long a fixture value lives.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FixtureScope {
+    /// Created for every definition requesting it, torn down after the definition
+    Definition,
+    /// Created once, torn down when the VM is cleaned up
+    Session,
+}
+
+/// A function registered with `@nc.fixture`.
+#[derive(Debug)]
+pub struct Fixture {
+    pub name: String,
+    pub func: Py<PyAny>,
+    pub scope: FixtureScope,
+}
+
+/// Created fixture values and the generators still waiting to be torn down.
+#[derive(Debug, Default)]
+pub struct FixtureValues {
+    values: HashMap<String, Py<PyAny>>,
+    teardowns: Vec<(String, Py<PyAny>)>,
+}
+
+impl FixtureValues {
+    /// Tears down generator fixtures in reverse order of creation.
+    ///
+    /// All fixtures are torn down even if one fails, the first error is returned.
+    pub fn teardown(&mut self, py: Python<'_>) -> PyResult<()> {
+        let mut result = Ok(());
+
+        while let Some((name, generator)) = self.teardowns.pop() {
+            let res = match generator.call_method0(py, "__next__") {
+                Err(e) if e.is_instance_of::<pyo3::exceptions::PyStopIteration>(py) => Ok(()),
+                Err(e) => Err(e),
+                Ok(_) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(format!(
+                    "Fixture `{}` yielded more than once",
+                    name
+                ))),
+            };
+
+            if result.is_ok() {
+                result = res;
+            }
+        }
+        self.values.clear();
+
+        result
+    }
+}
+
+/// Resolves the arguments of definitions, hooks and fixtures by parameter name.
+///
+/// A parameter named `client`, or an unknown first parameter, receives the definition's client.
+/// A parameter named `case` receives the case of a parametrized definition. Other parameters
+/// receive the fixture with the same name.
+pub struct FixtureResolver {
+    state: Arc<VmState>,
+    client: Py<PyAny>,
+    case: Option<Py<PyAny>>,
+    values: FixtureValues,
+}
+
+impl FixtureResolver {
+    pub fn new(state: Arc<VmState>, client: Py<PyAny>, case: Option<Py<PyAny>>) -> Self {
+        FixtureResolver {
+            state,
+            client,
+            case,
+            values: FixtureValues::default(),
+        }
+    }
+
+    /// Calls `func`, passing the client and fixtures it asks for.
+    pub fn call(&mut self, py: Python<'_>, func: &Py<PyAny>) -> PyResult<Py<PyAny>> {
+        self.call_resolving(py, func, &mut Vec::new())
+    }
+
+    /// Tears down definition scoped fixtures.
+    pub fn teardown(mut self, py: Python<'_>) -> PyResult<()> {
+        self.values.teardown(py)
+    }
+
+    fn call_resolving(
+        &mut self,
+        py: Python<'_>,
+        func: &Py<PyAny>,
+        stack: &mut Vec<String>,
+    ) -> PyResult<Py<PyAny>> {
+        let inspect = py.import("inspect")?;
+        let Ok(signature) = inspect.call_method1("signature", (func,)) else {
+            return func.call1(py, (self.client.clone_ref(py),));
+        };
+        let parameter = inspect.getattr("Parameter")?;
+        let positional_only = parameter.getattr("POSITIONAL_ONLY")?;
+        let var_positional = parameter.getattr("VAR_POSITIONAL")?;
+        let var_keyword = parameter.getattr("VAR_KEYWORD")?;
+        let empty = parameter.getattr("empty")?;
+
+        let args = PyTuple::empty(py);
+        let mut positional = Vec::new();
+        let kwargs = PyDict::new(py);
+
+        let params = signature.getattr("parameters")?.call_method0("values")?;
+        for (i, param) in params.try_iter()?.enumerate() {
+            let param = param?;
+            let name = param.getattr("name")?.extract::<String>()?;
+            let kind = param.getattr("kind")?;
+            if kind.eq(&var_positional)? || kind.eq(&var_keyword)? {
+                continue;
+            }
+
+            let value = if name == "client" {
+                self.client.clone_ref(py)
+            } else if let Some(case) = self.case.as_ref().filter(|_| name == "case") {
+                case.clone_ref(py)
+            } else if self.fixture_exists(&name) {
+                self.resolve(py, &name, stack)?
+            } else if i == 0 {
+                self.client.clone_ref(py)
+            } else if !param.getattr("default")?.is(&empty) {
+                continue;
+            } else {
+                return Err(PyErr::new::<pyo3::exceptions::PyLookupError, _>(format!(
+                    "Unknown fixture `{}`, requested by `{}`",
+                    name,
+                    func.getattr(py, "__name__")?
+                )));
+            };
+
+            if kind.eq(&positional_only)? {
+                positional.push(value);
+            } else {
+                kwargs.set_item(name, value)?;
+            }
+        }
+
+        let args = if positional.is_empty() {
+            args
+        } else {
+            PyTuple::new(py, positional)?
+        };
+
+        func.call(py, args, Some(&kwargs))
+    }
+
+    /// Returns the value of a fixture, creating it if needed.
+    fn resolve(
+        &mut self,
+        py: Python<'_>,
+        name: &str,
+        stack: &mut Vec<String>,
+    ) -> PyResult<Py<PyAny>> {
+        if let Some(value) = self.values.values.get(name) {
+            return Ok(value.clone_ref(py));
+        }
+        if let Some(value) = self.state.session_fixtures.lock().unwrap().values.get(name) {
+            return Ok(value.clone_ref(py));
+        }
+
+        if stack.iter().any(|n| n == name) {
+            return Err(PyErr::new::<pyo3::exceptions::PyRecursionError, _>(
+                format!("Fixture cycle: {} -> {}", stack.join(" -> "), name),
+            ));
+        }
+
+        let (func, scope) = self
+            .state
+            .fixtures
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|f| f.name == name)
+            .map(|f| (f.func.clone_ref(py), f.scope))
+            .ok_or_else(|| {
+                PyErr::new::<pyo3::exceptions::PyLookupError, _>(format!(
+                    "Unknown fixture `{}`",
+                    name
+                ))
+            })?;
+
+        stack.push(name.to_string());
+        let result = self.call_resolving(py, &func, stack);
+        stack.pop();
+        let result = result?;
+
+        let is_generator = py
+            .import("inspect")?
+            .call_method1("isgenerator", (&result,))?
+            .extract::<bool>()?;
+        let value = if is_generator {
+            result.call_method0(py, "__next__")?
+        } else {
+            result.clone_ref(py)
+        };
+
+        let state = self.state.clone();
+        let mut session = state.session_fixtures.lock().unwrap();
+        let values = match scope {
+            FixtureScope::Definition => &mut self.values,
+            FixtureScope::Session => &mut *session,
+        };
+        values.values.insert(name.to_string(), value.clone_ref(py));
+        if is_generator {
+            values.teardowns.push((name.to_string(), result));
+        }
+
+        Ok(value)
+    }
+
+    fn fixture_exists(&self, name: &str) -> bool {
+        self.state
+            .fixtures
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|f| f.name == name)
+    }
+}
+
+/// Decorator registering a fixture.
+///
+/// Used either bare, `@nc.fixture`, or with a scope, `@nc.fixture(scope="session")`.
+#[pyclass(name = "fixture")]
+pub struct PyFixture {
+    wraps: Option<Py<PyAny>>,
+    scope: FixtureScope,
+}
+
+impl PyFixture {
+    fn register(py: Python<'_>, wraps: Py<PyAny>, scope: FixtureScope) -> PyResult<Self> {
+        let name = wraps.getattr(py, "__name__")?.extract::<String>(py)?;
+
+        let state = VmState::current(py)?;
+        let mut fixtures = state.fixtures.lock().unwrap();
+        fixtures.retain(|f| f.name != name);
+        fixtures.push(Fixture {
+            name,
+            func: wraps.clone_ref(py),
+            scope,
+        });
+
+        Ok(PyFixture {
+            wraps: Some(wraps),
+            scope,
+        })
+    }
+}
+
+#[pymethods]
+impl PyFixture {
+    #[new]
+    #[pyo3(signature = (wraps = None, *, scope = "definition"))]
+    fn __new__(py: Python<'_>, wraps: Option<Py<PyAny>>, scope: &str) -> PyResult<Self> {
+        let scope = match scope {
+            "definition" => FixtureScope::Definition,
+            "session" => FixtureScope::Session,
+            _ => {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Unknown fixture scope `{}`, expected \"definition\" or \"session\"",
+                    scope
+                )));
+            }
+        };
+
+        match wraps {
+            Some(wraps) => PyFixture::register(py, wraps, scope),
+            None => Ok(PyFixture { wraps: None, scope }),
+        }
+    }
+
+    /// Calls the wrapped function, or registers `args[0]` when used as `@nc.fixture(...)`.
+    #[pyo3(signature = (*args, **kwargs))]
+    fn __call__(
+        &self,
+        py: Python<'_>,
+        args: &Bound<'_, PyTuple>,
+        kwargs: Option<&Bound<'_, PyDict>>,
+    ) -> PyResult<Py<PyAny>> {
+        if let Some(wraps) = &self.wraps {
+            return wraps.call(py, args, kwargs);
+        }
+
+        let wraps = args.get_item(0)?.unbind();
+        let fixture = PyFixture::register(py, wraps, self.scope)?;
+
+        Ok(Py::new(py, fixture)?.into_any())
+    }
+}
+
+pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyFixture>()?;
+
+    Ok(())
+}
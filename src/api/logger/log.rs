@@ -1,6 +1,15 @@
-use super::{LOGGER_CONFIG, PyLogLevel, PyLoggerConfig};
+use super::{PyLogLevel, PyLoggerConfig};
+use crate::{api::VmState, report::DefinitionError};
 use owo_colors::{OwoColorize, XtermColors};
-use pyo3::prelude::*;
+use pyo3::{create_exception, exceptions::PyBaseException, prelude::*};
+
+// Derives from BaseException like SystemExit, so `except Exception` does not swallow it
+create_exception!(
+    neocurl,
+    FatalError,
+    PyBaseException,
+    "Raised by `nc.fatal`, aborts the run after the cleanup hooks ran."
+);
 
 fn format_log(config: PyLoggerConfig, level: PyLogLevel, msg: &str) -> PyResult<String> {
     let timestamp = chrono::Utc::now().format(&config.datetime_format);
@@ -30,48 +39,126 @@ fn format_log(config: PyLoggerConfig, level: PyLogLevel, msg: &str) -> PyResult<
 }
 
 #[pyfunction]
-fn log(level: PyLogLevel, msg: String) -> PyResult<()> {
-    let config = LOGGER_CONFIG.lock().unwrap().clone();
+fn log(py: Python<'_>, level: PyLogLevel, msg: String) -> PyResult<()> {
+    write_log(&*VmState::current(py)?, level, msg)
+}
+
+/// Logs a message with the current logger config, the Rust side of `nc.log`.
+///
+/// Fatal messages raise [`FatalError`] to abort the run.
+pub fn write_log(state: &VmState, level: PyLogLevel, msg: String) -> PyResult<()> {
+    let config = state.logger_config.lock().unwrap().clone();
 
     if !config.level.less_than(&level) {
-        return Ok(());
+        return match level {
+            PyLogLevel::Fatal => Err(FatalError::new_err(msg)),
+            _ => Ok(()),
+        };
     }
 
-    println!("{}", format_log(config, level.clone(), &msg)?);
+    let plain_config = PyLoggerConfig {
+        use_colors: false,
+        ..config.clone()
+    };
+    state
+        .logs
+        .lock()
+        .unwrap()
+        .push(format_log(plain_config, level.clone(), &msg)?);
+
+    config
+        .output
+        .print(&format_log(config.clone(), level.clone(), &msg)?);
 
     if level == PyLogLevel::Fatal {
-        std::process::exit(1);
+        return Err(FatalError::new_err(msg));
+    }
+
+    Ok(())
+}
+
+/// Logs why a definition failed, followed by the Python traceback.
+///
+/// Assertion messages are highlighted. Only the first line is kept in the logs of the state,
+/// reports hold the full error.
+pub fn write_error(state: &VmState, error: &DefinitionError) -> PyResult<()> {
+    let config = state.logger_config.lock().unwrap().clone();
+
+    if !config.level.less_than(&PyLogLevel::Error) {
+        return Ok(());
+    }
+
+    let plain_config = PyLoggerConfig {
+        use_colors: false,
+        ..config.clone()
+    };
+    let headline = error.to_string();
+    state
+        .logs
+        .lock()
+        .unwrap()
+        .push(format_log(plain_config, PyLogLevel::Error, &headline)?);
+
+    let mut output = match (config.use_colors, error.is_assertion()) {
+        (true, true) => format!("{}: {}", error.kind.red(), error.message.yellow().bold()),
+        (true, false) => format!("{}: {}", error.kind.red(), error.message),
+        (false, _) => headline.clone(),
+    };
+    output = format_log(config.clone(), PyLogLevel::Error, &output)?;
+
+    // The formatted traceback ends with the exception itself, possibly prefixed by its module,
+    // which is the headline
+    let traceback = error
+        .formatted
+        .as_deref()
+        .map(|tb| match tb.strip_suffix(headline.as_str()) {
+            Some(rest) => rest.rsplit_once('\n').map_or("", |(rest, _)| rest),
+            None => tb,
+        })
+        .unwrap_or_default();
+    for line in traceback.lines() {
+        let line = match line.is_empty() {
+            true => String::new(),
+            false => format!("    {}", line),
+        };
+        match config.use_colors {
+            true => output.push_str(&format!("\n{}", line.color(XtermColors::DarkGray))),
+            false => output.push_str(&format!("\n{}", line)),
+        }
     }
 
+    config.output.print(&output);
+
     Ok(())
 }
 
 #[pyfunction]
-fn debug(msg: String) -> PyResult<()> {
-    log(PyLogLevel::Debug, msg)
+fn debug(py: Python<'_>, msg: String) -> PyResult<()> {
+    log(py, PyLogLevel::Debug, msg)
 }
 
 #[pyfunction]
-fn info(msg: String) -> PyResult<()> {
-    log(PyLogLevel::Info, msg)
+fn info(py: Python<'_>, msg: String) -> PyResult<()> {
+    log(py, PyLogLevel::Info, msg)
 }
 
 #[pyfunction]
-fn warn(msg: String) -> PyResult<()> {
-    log(PyLogLevel::Warn, msg)
+fn warn(py: Python<'_>, msg: String) -> PyResult<()> {
+    log(py, PyLogLevel::Warn, msg)
 }
 
 #[pyfunction]
-fn error(msg: String) -> PyResult<()> {
-    log(PyLogLevel::Error, msg)
+fn error(py: Python<'_>, msg: String) -> PyResult<()> {
+    log(py, PyLogLevel::Error, msg)
 }
 
 #[pyfunction]
-fn fatal(msg: String) -> PyResult<()> {
-    log(PyLogLevel::Fatal, msg)
+fn fatal(py: Python<'_>, msg: String) -> PyResult<()> {
+    log(py, PyLogLevel::Fatal, msg)
 }
 
 pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add("FatalError", module.py().get_type::<FatalError>())?;
     module.add_function(wrap_pyfunction!(log, module)?)?;
     module.add_function(wrap_pyfunction!(debug, module)?)?;
     module.add_function(wrap_pyfunction!(info, module)?)?;
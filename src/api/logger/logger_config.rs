@@ -1,6 +1,27 @@
-use super::{LOGGER_CONFIG, PyLogLevel};
+use super::PyLogLevel;
+use crate::api::VmState;
 use pyo3::prelude::*;
 
+/// Where log lines are printed. Logs of a definition are captured in its result either way.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum LogOutput {
+    Stdout,
+    Stderr,
+    /// Print nothing, for embedding and reports written to stdout
+    #[default]
+    Silent,
+}
+
+impl LogOutput {
+    pub fn print(self, line: &str) {
+        match self {
+            LogOutput::Stdout => println!("{}", line),
+            LogOutput::Stderr => eprintln!("{}", line),
+            LogOutput::Silent => {}
+        }
+    }
+}
+
 #[pyclass(name = "LoggerConfig")]
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct PyLoggerConfig {
@@ -14,6 +35,8 @@ pub struct PyLoggerConfig {
     pub datetime_format: String,
 
     pub context: Option<String>,
+
+    pub output: LogOutput,
 }
 
 impl Default for PyLoggerConfig {
@@ -23,6 +46,7 @@ impl Default for PyLoggerConfig {
             level: PyLogLevel::Info,
             datetime_format: "%Y-%m-%d %H:%M:%S".to_string(),
             context: None,
+            output: LogOutput::default(),
         }
     }
 }
@@ -38,15 +62,15 @@ impl PyLoggerConfig {
 }
 
 #[pyfunction]
-fn get_logger_config() -> PyResult<PyLoggerConfig> {
-    let config = LOGGER_CONFIG.lock().unwrap().clone();
+fn get_logger_config(py: Python<'_>) -> PyResult<PyLoggerConfig> {
+    let config = VmState::current(py)?.logger_config.lock().unwrap().clone();
 
     Ok(config)
 }
 
 #[pyfunction]
-fn set_logger_config(config: PyLoggerConfig) -> PyResult<()> {
-    *LOGGER_CONFIG.lock().unwrap() = config;
+fn set_logger_config(py: Python<'_>, config: PyLoggerConfig) -> PyResult<()> {
+    *VmState::current(py)?.logger_config.lock().unwrap() = config;
 
     Ok(())
 }
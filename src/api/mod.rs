@@ -1,35 +1,50 @@
+mod after_each;
+mod before_each;
 mod client;
+mod data;
 mod define;
 mod env;
+mod fixture;
 mod logger;
 mod on_cleanup;
 mod on_init;
+mod openapi;
+mod state;
+mod stub;
 mod tests;
 mod version;
 
-pub use client::PyClient;
-pub use logger::{LOGGER_CONFIG, PyLogLevel};
+pub use client::{PrintCurl, PyClient, PyMethod};
+pub use define::{Definition, DefinitionMeta};
+pub use fixture::{Fixture, FixtureResolver, FixtureScope, FixtureValues};
+pub use logger::{FatalError, LogOutput, PyLogLevel, PyLoggerConfig, write_error, write_log};
+pub use openapi::{Contract, VIOLATION_KIND, check_response};
+pub use state::{PyState, VmState, attach as attach_state};
+pub use stub::{Stub, dry_run_response};
 
-use once_cell::sync::Lazy;
 use pyo3::prelude::*;
-use std::sync::Mutex;
-
-pub static REGISTRY: Lazy<Mutex<Vec<Py<PyAny>>>> = Lazy::new(|| Mutex::new(Vec::new()));
-pub static ON_INIT: Lazy<Mutex<Option<Py<PyAny>>>> = Lazy::new(|| Mutex::new(None));
-pub static ON_CLEANUP: Lazy<Mutex<Option<Py<PyAny>>>> = Lazy::new(|| Mutex::new(None));
-pub static TESTS: Lazy<Mutex<(u32, u32)>> = Lazy::new(|| Mutex::new((0, 0)));
-pub static CALLS: Lazy<Mutex<(u32, u32)>> = Lazy::new(|| Mutex::new((0, 0)));
+use std::sync::Arc;
 
 #[pymodule(name = "neocurl")]
 pub fn neocurl_py_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    after_each::register(m)?;
+    before_each::register(m)?;
     client::register(m)?;
+    data::register(m)?;
     define::register(m)?;
     env::register(m)?;
+    fixture::register(m)?;
     logger::register(m)?;
     on_cleanup::register(m)?;
     on_init::register(m)?;
+    openapi::register(m)?;
+    stub::register(m)?;
     tests::register(m)?;
     version::register(m)?;
 
+    // Replaced by the state of the VM importing the module
+    m.add_class::<PyState>()?;
+    state::attach(m, Arc::new(VmState::default()))?;
+
     Ok(())
 }
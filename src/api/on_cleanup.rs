@@ -1,19 +1,28 @@
-use super::ON_CLEANUP;
+use super::VmState;
 use pyo3::{
     prelude::*,
     types::{PyDict, PyTuple},
 };
 
+/// Decorator registering a function that runs when NeoCurl finishes, even after failures.
+///
+/// Several functions can be registered, they run in declaration order.
 #[pyclass(name = "on_cleanup")]
-pub struct PyOnCleanup {}
+pub struct PyOnCleanup {
+    wraps: Py<PyAny>,
+}
 
 #[pymethods]
 impl PyOnCleanup {
     #[new]
-    fn __new__(wraps: Py<PyAny>) -> Self {
-        ON_CLEANUP.lock().unwrap().replace(wraps);
+    fn __new__(py: Python<'_>, wraps: Py<PyAny>) -> PyResult<Self> {
+        VmState::current(py)?
+            .on_cleanup
+            .lock()
+            .unwrap()
+            .push(wraps.clone_ref(py));
 
-        PyOnCleanup {}
+        Ok(PyOnCleanup { wraps })
     }
 
     #[pyo3(signature = (*args, **kwargs))]
@@ -23,13 +32,7 @@ impl PyOnCleanup {
         args: &Bound<'_, PyTuple>,
         kwargs: Option<&Bound<'_, PyDict>>,
     ) -> PyResult<Py<PyAny>> {
-        let on_init = ON_CLEANUP.lock().unwrap();
-        let on_init_ref = on_init.as_ref().ok_or_else(|| {
-            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("on_init function not set")
-        })?;
-        let ret = on_init_ref.call(py, args, kwargs)?;
-
-        Ok(ret)
+        self.wraps.call(py, args, kwargs)
     }
 }
 
@@ -1,19 +1,28 @@
-use super::ON_INIT;
+use super::VmState;
 use pyo3::{
     prelude::*,
     types::{PyDict, PyTuple},
 };
 
+/// Decorator registering a function that runs after the script is loaded.
+///
+/// Several functions can be registered, they run in declaration order.
 #[pyclass(name = "on_init")]
-pub struct PyOnInit {}
+pub struct PyOnInit {
+    wraps: Py<PyAny>,
+}
 
 #[pymethods]
 impl PyOnInit {
     #[new]
-    fn __new__(wraps: Py<PyAny>) -> Self {
-        ON_INIT.lock().unwrap().replace(wraps);
+    fn __new__(py: Python<'_>, wraps: Py<PyAny>) -> PyResult<Self> {
+        VmState::current(py)?
+            .on_init
+            .lock()
+            .unwrap()
+            .push(wraps.clone_ref(py));
 
-        PyOnInit {}
+        Ok(PyOnInit { wraps })
     }
 
     #[pyo3(signature = (*args, **kwargs))]
@@ -23,13 +32,7 @@ impl PyOnInit {
         args: &Bound<'_, PyTuple>,
         kwargs: Option<&Bound<'_, PyDict>>,
     ) -> PyResult<Py<PyAny>> {
-        let on_init = ON_INIT.lock().unwrap();
-        let on_init_ref = on_init.as_ref().ok_or_else(|| {
-            PyErr::new::<pyo3::exceptions::PyRuntimeError, _>("on_init function not set")
-        })?;
-        let ret = on_init_ref.call(py, args, kwargs)?;
-
-        Ok(ret)
+        self.wraps.call(py, args, kwargs)
     }
 }
 
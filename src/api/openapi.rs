@@ -0,0 +1,556 @@
+use super::{
+    VmState,
+    client::{PyRequest, PyResponse},
+};
+use crate::openapi::Spec;
+use jsonschema::{Draft, Validator};
+use pyo3::{prelude::*, wrap_pyfunction};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Kind of the error failing a definition whose responses do not match the spec.
+pub const VIOLATION_KIND: &str = "ContractViolation";
+
+/// An OpenAPI document responses are checked against.
+pub struct Contract {
+    spec: Spec,
+    path: PathBuf,
+    draft: Draft,
+    /// Compiled response schemas, by operation, status and media type
+    validators: Mutex<HashMap<String, Arc<Validator>>>,
+}
+
+impl Contract {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(Contract::new(Spec::load(path)?, path))
+    }
+
+    /// A contract for a loaded document, `path` naming it in errors.
+    fn new(spec: Spec, path: &Path) -> Self {
+        // OpenAPI 3.0 schemas are based on JSON Schema draft 4, 3.1 uses 2020-12
+        let draft = match spec.document()["openapi"].as_str() {
+            Some(version) if version.starts_with("3.0") => Draft::Draft4,
+            _ => Draft::Draft202012,
+        };
+
+        Contract {
+            spec,
+            path: path.to_path_buf(),
+            draft,
+            validators: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Checks a response against the operation documenting its request, returning the
+    /// violations. `None` if the spec does not document the path of the request.
+    pub fn check(&self, request: &PyRequest, response: &PyResponse) -> Option<Vec<String>> {
+        let url = url::Url::parse(&request.url).ok()?;
+        let template = self.spec.match_path(url.path())?;
+        let method = request.method.to_string();
+        let name = format!("{} {}", method, template);
+
+        let Some(operation) = self.spec.operation(&method, template) else {
+            return Some(vec![format!("{}: the method is not documented", name)]);
+        };
+
+        let responses = &operation.value["responses"];
+        let status = response.status_code.to_string();
+        let class = format!("{}XX", &status[..1]);
+        let lower_class = class.to_lowercase();
+        let Some((key, documented)) = [status.as_str(), &class, &lower_class, "default"]
+            .into_iter()
+            .find_map(|key| Some((key, responses.get(key)?)))
+        else {
+            return Some(vec![format!(
+                "{}: status {} is not documented",
+                name, response.status_code
+            )]);
+        };
+        let documented = self.spec.resolve(documented);
+        let validator_key = format!("{} {}", name, key);
+        let name = format!("{} {}", name, response.status_code);
+
+        let mut violations = Vec::new();
+        for (header, value) in documented["headers"].as_object().into_iter().flatten() {
+            let present = response
+                .headers
+                .keys()
+                .any(|k| k.eq_ignore_ascii_case(header));
+            if self.spec.resolve(value)["required"].as_bool() == Some(true) && !present {
+                violations.push(format!("{}: header {} is required", name, header));
+            }
+        }
+
+        violations.extend(self.check_body(&name, &validator_key, documented, response));
+
+        Some(violations)
+    }
+
+    fn check_body(
+        &self,
+        name: &str,
+        key: &str,
+        documented: &Value,
+        response: &PyResponse,
+    ) -> Vec<String> {
+        let Some(content) = documented["content"].as_object().filter(|c| !c.is_empty()) else {
+            return Vec::new();
+        };
+        let Some(body) = response.body.as_deref().filter(|b| !b.is_empty()) else {
+            return Vec::new();
+        };
+
+        let content_type = response
+            .headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("content-type"))
+            .map(|(_, v)| {
+                v.split(';')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_lowercase()
+            });
+        let media = match &content_type {
+            Some(content_type) => {
+                let any_subtype =
+                    format!("{}/*", content_type.split('/').next().unwrap_or_default());
+                [content_type.as_str(), &any_subtype, "*/*"]
+                    .into_iter()
+                    .find_map(|key| content.get_key_value(key))
+            }
+            None if content.len() == 1 => content.iter().next(),
+            None => None,
+        };
+        let Some((media, value)) = media else {
+            let documented: Vec<&str> = content.keys().map(String::as_str).collect();
+            return vec![format!(
+                "{}: content type {} is not documented, expected {}",
+                name,
+                content_type.as_deref().unwrap_or("(none)"),
+                documented.join(" or ")
+            )];
+        };
+
+        let json = content_type
+            .as_deref()
+            .unwrap_or(media)
+            .split_once('/')
+            .is_some_and(|(_, subtype)| subtype == "json" || subtype.ends_with("+json"));
+        let Some(schema) = value.get("schema").filter(|_| json) else {
+            return Vec::new();
+        };
+
+        let instance: Value = match serde_json::from_str(body) {
+            Ok(instance) => instance,
+            Err(e) => return vec![format!("{}: body is not valid JSON: {}", name, e)],
+        };
+
+        let validator = match self.validator(&format!("{} {}", key, media), schema) {
+            Ok(validator) => validator,
+            Err(e) => {
+                return vec![format!(
+                    "{}: invalid schema in {}: {}",
+                    name,
+                    self.path.display(),
+                    e
+                )];
+            }
+        };
+
+        validator
+            .iter_errors(&instance)
+            .map(|error| {
+                let pointer = error.instance_path.as_str().to_string();
+                match pointer.is_empty() {
+                    true => format!("{}: body: {}", name, error),
+                    false => format!("{}: body at {}: {}", name, pointer, error),
+                }
+            })
+            .collect()
+    }
+
+    /// Compiles a response schema, with the components of the document it may refer to.
+    fn validator(&self, key: &str, schema: &Value) -> Result<Arc<Validator>, String> {
+        if let Some(validator) = self.validators.lock().unwrap().get(key) {
+            return Ok(validator.clone());
+        }
+
+        let mut root = schema.clone();
+        if let Value::Object(root) = &mut root {
+            root.entry("components")
+                .or_insert_with(|| self.spec.document()["components"].clone());
+        }
+        if self.draft == Draft::Draft4 {
+            nullable_types(&mut root);
+        }
+
+        let validator = jsonschema::options()
+            .with_draft(self.draft)
+            .build(&root)
+            .map_err(|e| e.to_string())?;
+        let validator = Arc::new(validator);
+        self.validators
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), validator.clone());
+
+        Ok(validator)
+    }
+}
+
+/// Turns the `nullable: true` of OpenAPI 3.0 schemas into a `null` type JSON Schema knows.
+fn nullable_types(schema: &mut Value) {
+    match schema {
+        Value::Object(object) => {
+            if object.get("nullable") == Some(&Value::Bool(true)) {
+                if let Some(Value::String(kind)) = object.get("type") {
+                    let kind = Value::String(kind.clone());
+                    object.insert("type".to_string(), Value::Array(vec![kind, "null".into()]));
+                }
+                if let Some(Value::Array(values)) = object.get_mut("enum") {
+                    values.push(Value::Null);
+                }
+            }
+            object.values_mut().for_each(nullable_types);
+        }
+        Value::Array(values) => values.iter_mut().for_each(nullable_types),
+        _ => {}
+    }
+}
+
+/// Checks a response against the specs loaded with `nc.openapi`, the first one documenting the
+/// request path. Violations fail the running definition once it finishes.
+pub fn check_response(state: &VmState, request: &PyRequest, response: &PyResponse) {
+    let contracts: Vec<Arc<Contract>> = state.contracts.lock().unwrap().clone();
+    let Some(violations) = contracts
+        .iter()
+        .find_map(|contract| contract.check(request, response))
+    else {
+        return;
+    };
+
+    // Async requests often violate the spec in the same way many times
+    let mut recorded = state.violations.lock().unwrap();
+    for violation in violations {
+        if !recorded.contains(&violation) {
+            recorded.push(violation);
+        }
+    }
+}
+
+/// Checks every response received from now on against an OpenAPI 3 document.
+///
+/// The status code has to be documented for the operation, JSON bodies have to match their
+/// schema and required headers have to be present. Requests to paths the document does not
+/// describe are not checked. Relative paths are relative to the script.
+#[pyfunction]
+fn openapi(py: Python<'_>, path: PathBuf) -> PyResult<()> {
+    let state = VmState::current(py)?;
+    let contract = Contract::load(&state.script_path(&path))
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("{:#}", e)))?;
+
+    state.contracts.lock().unwrap().push(Arc::new(contract));
+
+    Ok(())
+}
+
+pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(openapi, module)?)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::PyMethod;
+
+    const SPEC_3_0: &str = r##"
+openapi: 3.0.3
+paths:
+  /users/{id}:
+    get:
+      responses:
+        200:
+          description: The user
+          headers:
+            X-Rate-Limit: { required: true, schema: { type: integer } }
+          content:
+            application/json:
+              schema: { $ref: "#/components/schemas/User" }
+        4XX:
+          description: Client error
+          content:
+            application/json:
+              schema: { $ref: "#/components/schemas/Error" }
+  /users:
+    post:
+      responses:
+        201: { description: Created }
+components:
+  schemas:
+    User:
+      type: object
+      required: [id, name]
+      properties:
+        id: { type: integer }
+        name: { type: string }
+        manager: { type: string, nullable: true }
+        role: { type: string, enum: [admin, user], nullable: true }
+        tags: { type: array, items: { type: string } }
+    Error:
+      type: object
+      required: [message]
+      properties:
+        message: { type: string }
+"##;
+
+    const SPEC_3_1: &str = r#"
+openapi: 3.1.0
+paths:
+  /items:
+    get:
+      responses:
+        200:
+          description: Items
+          content:
+            application/json:
+              schema:
+                type: array
+                items: { type: [string, "null"] }
+        default:
+          description: Anything else
+          content:
+            application/problem+json:
+              schema:
+                type: object
+                required: [title]
+"#;
+
+    fn load(spec: &str) -> Contract {
+        Contract::new(Spec::parse(spec).unwrap(), Path::new("api.yaml"))
+    }
+
+    fn request(method: PyMethod, url: &str) -> PyRequest {
+        PyRequest {
+            url: url.to_string(),
+            method,
+            headers: HashMap::new(),
+            params: HashMap::new(),
+            body: None,
+            timeout: 0,
+            connect_timeout: None,
+            read_timeout: None,
+            fresh_connection: false,
+            insecure: false,
+        }
+    }
+
+    fn response(status_code: u16, headers: &[(&str, &str)], body: &str) -> PyResponse {
+        PyResponse {
+            status: status_code.to_string(),
+            status_code,
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: Some(body.to_string()),
+            duration: 0,
+            request: None,
+            started: chrono::Utc::now(),
+            error: None,
+        }
+    }
+
+    fn check(
+        contract: &Contract,
+        method: PyMethod,
+        url: &str,
+        response: PyResponse,
+    ) -> Option<Vec<String>> {
+        contract.check(&request(method, url), &response)
+    }
+
+    const JSON: (&str, &str) = ("Content-Type", "application/json; charset=utf-8");
+    const RATE_LIMIT: (&str, &str) = ("x-rate-limit", "10");
+
+    #[test]
+    fn valid_response() {
+        let contract = load(SPEC_3_0);
+        let user = r#"{"id": 1, "name": "neo", "tags": ["a"]}"#;
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/users/1",
+                response(200, &[JSON, RATE_LIMIT], user)
+            ),
+            Some(vec![])
+        );
+        // Paths the spec does not describe are not checked
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/orders",
+                response(500, &[], "")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn undocumented_status_and_method() {
+        let contract = load(SPEC_3_0);
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/users/1",
+                response(500, &[], "")
+            ),
+            Some(vec![
+                "GET /users/{id}: status 500 is not documented".to_string()
+            ])
+        );
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Delete,
+                "https://example.com/users/1",
+                response(204, &[], "")
+            ),
+            Some(vec![
+                "DELETE /users/{id}: the method is not documented".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn status_class_and_default() {
+        let contract = load(SPEC_3_0);
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/users/1",
+                response(404, &[JSON], "{}")
+            ),
+            Some(vec![
+                "GET /users/{id} 404: body: \"message\" is a required property".to_string()
+            ])
+        );
+
+        let contract = load(SPEC_3_1);
+        let problem = ("Content-Type", "application/problem+json");
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/items",
+                response(503, &[problem], r#"{"title": "Down"}"#)
+            ),
+            Some(vec![])
+        );
+        assert_eq!(
+            check(&contract, PyMethod::Get, "https://example.com/items", response(503, &[JSON], "{}")),
+            Some(vec![
+                "GET /items 503: content type application/json is not documented, expected application/problem+json".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn missing_required_header() {
+        let contract = load(SPEC_3_0);
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/users/1",
+                response(200, &[JSON], r#"{"id": 1, "name": "neo"}"#)
+            ),
+            Some(vec![
+                "GET /users/{id} 200: header X-Rate-Limit is required".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn schema_violations_at_pointers() {
+        let contract = load(SPEC_3_0);
+        let user = r#"{"id": "1", "name": "neo", "tags": ["a", 2]}"#;
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/users/1",
+                response(200, &[JSON, RATE_LIMIT], user)
+            ),
+            Some(vec![
+                "GET /users/{id} 200: body at /id: \"1\" is not of type \"integer\"".to_string(),
+                "GET /users/{id} 200: body at /tags/1: 2 is not of type \"string\"".to_string(),
+            ])
+        );
+
+        let contract = load(SPEC_3_1);
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/items",
+                response(200, &[JSON], r#"["a", null, 1]"#)
+            ),
+            Some(vec![
+                "GET /items 200: body at /2: 1 is not of types \"null\", \"string\"".to_string()
+            ])
+        );
+        assert_eq!(
+            check(&contract, PyMethod::Get, "https://example.com/items", response(200, &[JSON], "[")),
+            Some(vec![
+                "GET /items 200: body is not valid JSON: EOF while parsing a list at line 1 column 1".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn nullable() {
+        let contract = load(SPEC_3_0);
+        let user = r#"{"id": 1, "name": "neo", "manager": null, "role": null}"#;
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/users/1",
+                response(200, &[JSON, RATE_LIMIT], user)
+            ),
+            Some(vec![])
+        );
+
+        // Only nullable properties accept null
+        let user = r#"{"id": 1, "name": null}"#;
+        assert_eq!(
+            check(
+                &contract,
+                PyMethod::Get,
+                "https://example.com/users/1",
+                response(200, &[JSON, RATE_LIMIT], user)
+            ),
+            Some(vec![
+                "GET /users/{id} 200: body at /name: null is not of type \"string\"".to_string()
+            ])
+        );
+
+        let mut schema = serde_json::json!({"type": "string", "enum": ["a"], "nullable": true});
+        nullable_types(&mut schema);
+        assert_eq!(
+            schema,
+            serde_json::json!({"type": ["string", "null"], "enum": ["a", null], "nullable": true})
+        );
+    }
+}
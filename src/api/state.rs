@@ -0,0 +1,89 @@
+use super::{Contract, Definition, Fixture, FixtureValues, PrintCurl, PyLoggerConfig, Stub};
+use crate::report::{Assertions, RequestRecord};
+use pyo3::prelude::*;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Everything a script registers and records while it runs, owned by its [`Vm`](crate::vm::Vm).
+///
+/// The state is attached to the `neocurl` module of the VM, so functions called from Python
+/// find it through the interpreter.
+#[derive(Default)]
+pub struct VmState {
+    pub registry: Mutex<Vec<Definition>>,
+    pub on_init: Mutex<Vec<Py<PyAny>>>,
+    pub on_cleanup: Mutex<Vec<Py<PyAny>>>,
+    pub before_each: Mutex<Vec<Py<PyAny>>>,
+    pub after_each: Mutex<Vec<Py<PyAny>>>,
+    pub fixtures: Mutex<Vec<Fixture>>,
+    pub session_fixtures: Mutex<FixtureValues>,
+    /// Passed and failed tests, counting definitions and assertions
+    pub tests: Mutex<(u32, u32)>,
+    /// Passed and failed definitions
+    pub calls: Mutex<(u32, u32)>,
+    pub assertions: Mutex<Assertions>,
+    /// Requests sent by the running definition, or outside of definitions since the last one
+    pub requests: Mutex<Vec<RequestRecord>>,
+    /// Requests sent outside of definitions: by on_init, on_cleanup and session fixture teardowns
+    pub script_requests: Mutex<Vec<RequestRecord>>,
+    pub logger_config: Mutex<PyLoggerConfig>,
+    /// Whether the client prints the requests it sends as curl commands
+    pub print_curl: Mutex<PrintCurl>,
+    /// Whether requests are answered by stubs instead of being sent
+    pub dry_run: Mutex<bool>,
+    pub stubs: Mutex<Vec<Stub>>,
+    /// Specs responses are checked against, loaded with `nc.openapi`
+    pub contracts: Mutex<Vec<Arc<Contract>>>,
+    /// Violations of the specs by responses of the running definition
+    pub violations: Mutex<Vec<String>>,
+    /// Uncolored log lines printed since the last time they were taken
+    pub logs: Mutex<Vec<String>>,
+    /// Variables returned by `nc.env` before the environment is looked at
+    pub env: Mutex<HashMap<String, String>>,
+    /// Namespace of the file being loaded, prefixed to the definitions it registers
+    pub namespace: Mutex<Option<String>>,
+    /// Directory of the file being loaded, or of the first file once the script is loaded
+    pub script_dir: Mutex<PathBuf>,
+}
+
+impl VmState {
+    /// Returns the state of the `neocurl` module the interpreter currently imports.
+    pub fn current(py: Python<'_>) -> PyResult<Arc<VmState>> {
+        let module = py.import("sys")?.getattr("modules")?.get_item("neocurl")?;
+        let state = module.getattr(STATE_ATTR)?;
+
+        Ok(state.downcast::<PyState>()?.get().0.clone())
+    }
+
+    /// Resolves a path relative to the directory of the script, absolute paths are kept.
+    pub fn script_path(&self, path: &Path) -> PathBuf {
+        self.script_dir.lock().unwrap().join(path)
+    }
+
+    /// Forgets everything the script registered, keeping counters and logger config.
+    pub fn clear_registrations(&self) {
+        self.registry.lock().unwrap().clear();
+        self.on_init.lock().unwrap().clear();
+        self.on_cleanup.lock().unwrap().clear();
+        self.before_each.lock().unwrap().clear();
+        self.after_each.lock().unwrap().clear();
+        self.fixtures.lock().unwrap().clear();
+        self.stubs.lock().unwrap().clear();
+        self.contracts.lock().unwrap().clear();
+    }
+}
+
+/// Name of the `neocurl` module attribute holding the state.
+pub const STATE_ATTR: &str = "__state__";
+
+/// Python handle to a [`VmState`].
+#[pyclass(name = "State", frozen)]
+pub struct PyState(pub Arc<VmState>);
+
+/// Attaches `state` to a `neocurl` module.
+pub fn attach(module: &Bound<'_, PyModule>, state: Arc<VmState>) -> PyResult<()> {
+    module.setattr(STATE_ATTR, Py::new(module.py(), PyState(state))?)
+}
@@ -0,0 +1,199 @@
+use super::{
+    VmState,
+    client::{PyMethod, PyRequest, PyResponse},
+};
+use pyo3::{
+    prelude::*,
+    types::{PyDict, PyString},
+};
+use std::collections::HashMap;
+
+/// A response returned instead of sending matching requests in a dry run.
+#[derive(Debug)]
+pub struct Stub {
+    /// Method to match, any if `None`
+    pub method: Option<PyMethod>,
+    /// Glob matched against the URL, without query parameters
+    pub url: glob::Pattern,
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: Option<String>,
+    /// Function called with the request, its return value replaces the fields above
+    pub responder: Option<Py<PyAny>>,
+}
+
+impl Stub {
+    fn matches(&self, request: &PyRequest) -> bool {
+        self.method.as_ref().is_none_or(|m| *m == request.method)
+            && (self.url.as_str() == request.url || self.url.matches(&request.url))
+    }
+}
+
+/// Returns the response of the first stub matching the request, or an empty `200 OK`.
+pub fn dry_run_response(
+    py: Python<'_>,
+    state: &VmState,
+    request: &PyRequest,
+    started: chrono::DateTime<chrono::Utc>,
+) -> PyResult<PyResponse> {
+    let (mut status, mut headers, mut body, responder) = {
+        let stubs = state.stubs.lock().unwrap();
+        match stubs.iter().find(|stub| stub.matches(request)) {
+            Some(stub) => (
+                stub.status,
+                stub.headers.clone(),
+                stub.body.clone(),
+                stub.responder.as_ref().map(|r| r.clone_ref(py)),
+            ),
+            None => (200, HashMap::new(), None, None),
+        }
+    };
+
+    // Called without holding the lock, the responder may register stubs itself
+    if let Some(responder) = responder {
+        let response = responder.call1(py, (request.clone(),))?;
+        let response = response.bind(py);
+
+        if let Ok(dict) = response.downcast::<PyDict>() {
+            if let Some(value) = dict.get_item("status")? {
+                status = value.extract()?;
+            }
+            if let Some(value) = dict.get_item("headers")? {
+                headers = value.extract()?;
+            }
+            if let Some(value) = dict.get_item("body")? {
+                body = body_text(&value, &mut headers)?;
+            }
+        } else if !response.is_none() {
+            body = body_text(response, &mut headers)?;
+        }
+    }
+
+    let status_text = reqwest::StatusCode::from_u16(status)
+        .map(|s| s.to_string())
+        .map_err(|_| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid stub status: {}",
+                status
+            ))
+        })?;
+
+    Ok(PyResponse {
+        status: status_text,
+        status_code: status,
+        headers,
+        body,
+        duration: 0,
+        request: Some(request.clone()),
+        started,
+        error: None,
+    })
+}
+
+/// Text of a stub body. Values other than strings are sent as JSON.
+fn body_text(
+    body: &Bound<'_, PyAny>,
+    headers: &mut HashMap<String, String>,
+) -> PyResult<Option<String>> {
+    if body.is_none() {
+        return Ok(None);
+    }
+    if let Ok(text) = body.downcast::<PyString>() {
+        return Ok(Some(text.to_string()));
+    }
+
+    let json = body
+        .py()
+        .import("json")?
+        .call_method1("dumps", (body,))?
+        .extract::<String>()?;
+    if !headers
+        .keys()
+        .any(|k| k.eq_ignore_ascii_case("content-type"))
+    {
+        headers.insert("content-type".to_string(), "application/json".to_string());
+    }
+
+    Ok(Some(json))
+}
+
+/// Registers the response of requests matching `method` and `url` in a dry run.
+///
+/// Used either directly, `nc.stub("DELETE", "*/users/*", status=204)`, or as a decorator of a
+/// function returning the response for the request it is called with.
+#[pyclass(name = "stub")]
+pub struct PyStub {
+    /// Position of the stub in the state, to attach the decorated function
+    index: usize,
+}
+
+#[pymethods]
+impl PyStub {
+    #[new]
+    #[pyo3(signature = (method, url, *, status = 200, body = None, headers = None))]
+    fn __new__(
+        py: Python<'_>,
+        method: &Bound<'_, PyAny>,
+        url: &str,
+        status: u16,
+        body: Option<&Bound<'_, PyAny>>,
+        headers: Option<HashMap<String, String>>,
+    ) -> PyResult<Self> {
+        let method = match method.extract::<PyMethod>() {
+            Ok(method) => Some(method),
+            Err(_) if method.is_none() => None,
+            Err(_) => match method.extract::<String>()?.as_str() {
+                "*" => None,
+                other => Some(other.parse::<PyMethod>().map_err(|_| {
+                    PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                        "Unknown method `{}`",
+                        other
+                    ))
+                })?),
+            },
+        };
+        let url = glob::Pattern::new(url).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Invalid URL pattern `{}`: {}",
+                url, e
+            ))
+        })?;
+
+        let mut headers = headers.unwrap_or_default();
+        let body = match body {
+            Some(body) => body_text(body, &mut headers)?,
+            None => None,
+        };
+
+        let state = VmState::current(py)?;
+        let mut stubs = state.stubs.lock().unwrap();
+        stubs.push(Stub {
+            method,
+            url,
+            status,
+            headers,
+            body,
+            responder: None,
+        });
+
+        Ok(PyStub {
+            index: stubs.len() - 1,
+        })
+    }
+
+    /// Makes the decorated function respond to the stubbed requests.
+    fn __call__(&self, py: Python<'_>, responder: Py<PyAny>) -> PyResult<Py<PyAny>> {
+        let state = VmState::current(py)?;
+        if let Some(stub) = state.stubs.lock().unwrap().get_mut(self.index) {
+            stub.responder = Some(responder.clone_ref(py));
+        }
+
+        Ok(responder)
+    }
+}
+
+pub fn register(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyStub>()?;
+
+    Ok(())
+}
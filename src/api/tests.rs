@@ -1,29 +1,39 @@
-use super::TESTS;
+use super::VmState;
 use pyo3::{prelude::*, wrap_pyfunction};
 
 #[pyfunction]
-fn assert_t(cond: bool) -> PyResult<bool> {
-    let mut tests = TESTS.lock().unwrap();
+fn assert_t(py: Python<'_>, cond: bool) -> PyResult<bool> {
+    let state = VmState::current(py)?;
+    let mut tests = state.tests.lock().unwrap();
     let pass = cond;
 
+    let mut assertions = state.assertions.lock().unwrap();
+
     if pass {
         tests.0 += 1;
+        assertions.passed += 1;
     } else {
         tests.1 += 1;
+        assertions.failed += 1;
     }
 
     Ok(pass)
 }
 
 #[pyfunction]
-fn assert_f(cond: bool) -> PyResult<bool> {
-    let mut tests = TESTS.lock().unwrap();
+fn assert_f(py: Python<'_>, cond: bool) -> PyResult<bool> {
+    let state = VmState::current(py)?;
+    let mut tests = state.tests.lock().unwrap();
     let pass = !cond;
 
+    let mut assertions = state.assertions.lock().unwrap();
+
     if pass {
         tests.0 += 1;
+        assertions.passed += 1;
     } else {
         tests.1 += 1;
+        assertions.failed += 1;
     }
 
     Ok(pass)
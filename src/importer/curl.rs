@@ -0,0 +1,473 @@
+//! Parsing curl command lines.
+
+use super::{GeneratedDefinition, identifier, set_default_header};
+use crate::api::PyMethod;
+use anyhow::{Context, Result};
+use base64::Engine;
+
+/// Short options followed by a value, which can be attached like in `-XPOST`.
+const SHORT_OPTIONS_WITH_VALUE: &str = "XHdubAemow";
+
+/// Options taking no value that do not change the request.
+const IGNORED_FLAGS: &[&str] = &[
+    "-s",
+    "--silent",
+    "-S",
+    "--show-error",
+    "-L",
+    "--location",
+    "-v",
+    "--verbose",
+    "-i",
+    "--include",
+    "-f",
+    "--fail",
+    "-g",
+    "--globoff",
+    "-N",
+    "--no-buffer",
+    "--http1.1",
+    "--http2",
+    "--compressed",
+];
+
+/// Options taking a value that do not change the request.
+const IGNORED_OPTIONS: &[&str] = &["-o", "--output", "-w", "--write-out"];
+
+/// Parses curl commands, one per line, into definitions.
+///
+/// Lines are split like a shell would, including `\` continuations and the `$'...'` quoting
+/// browsers use when copying requests. Lines not starting with `curl` and `#` comments are
+/// ignored.
+pub fn parse(text: &str) -> Result<Vec<GeneratedDefinition>> {
+    split_commands(text)?
+        .into_iter()
+        .filter(|words| words.first().is_some_and(|w| w == "curl"))
+        .map(|words| {
+            let command = words.join(" ");
+            parse_command(&words[1..]).context(format!("Failed to import `{}`", command))
+        })
+        .collect()
+}
+
+/// Quotes separate command line arguments back into one command, for `ncurl import curl curl
+/// -X POST ...` without quotes.
+pub fn join_args(args: &[String]) -> String {
+    let mut words: Vec<String> = args
+        .iter()
+        .map(|arg| format!("'{}'", arg.replace('\'', r"'\''")))
+        .collect();
+    if args.first().is_none_or(|arg| arg != "curl") {
+        words.insert(0, "curl".to_string());
+    }
+
+    words.join(" ")
+}
+
+/// Parses the arguments of one curl command, without `curl` itself.
+fn parse_command(args: &[String]) -> Result<GeneratedDefinition> {
+    let mut method: Option<String> = None;
+    let mut url: Option<String> = None;
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut data: Vec<String> = Vec::new();
+    let mut get = false;
+    let mut head = false;
+    let mut timeout = None;
+    let mut insecure = false;
+
+    let args = expand_short_options(args);
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        let option = option.as_str();
+        let mut value = || -> Result<String> {
+            args.next()
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for {}", option))
+        };
+
+        match option {
+            "-X" | "--request" => method = Some(value()?.to_uppercase()),
+            "-H" | "--header" => {
+                let header = value()?;
+                if let Some((key, value)) = header.split_once(':') {
+                    headers.push((key.trim().to_string(), value.trim().to_string()));
+                }
+            }
+            "-d" | "--data" | "--data-ascii" | "--data-binary" => {
+                let value = value()?;
+                if value.starts_with('@') {
+                    return Err(anyhow::anyhow!(
+                        "Reading data from a file is not supported: {}",
+                        value
+                    ));
+                }
+                data.push(value);
+            }
+            "--data-raw" => data.push(value()?),
+            "--data-urlencode" => data.push(urlencode_data(&value()?)?),
+            "--json" => {
+                data.push(value()?);
+                set_default_header(&mut headers, "Content-Type", "application/json");
+                set_default_header(&mut headers, "Accept", "application/json");
+            }
+            "-u" | "--user" => {
+                let user = value()?;
+                let user = match user.contains(':') {
+                    true => user,
+                    false => format!("{}:", user),
+                };
+                let credentials = base64::engine::general_purpose::STANDARD.encode(user);
+                headers.push((
+                    "Authorization".to_string(),
+                    format!("Basic {}", credentials),
+                ));
+            }
+            "-b" | "--cookie" => {
+                let cookie = value()?;
+                if !cookie.contains('=') {
+                    return Err(anyhow::anyhow!(
+                        "Reading cookies from a file is not supported: {}",
+                        cookie
+                    ));
+                }
+                headers.push(("Cookie".to_string(), cookie));
+            }
+            "-A" | "--user-agent" => headers.push(("User-Agent".to_string(), value()?)),
+            "-e" | "--referer" => headers.push(("Referer".to_string(), value()?)),
+            "-m" | "--max-time" => {
+                let seconds = value()?;
+                let seconds: f64 = seconds
+                    .parse()
+                    .context(format!("Invalid --max-time: {}", seconds))?;
+                timeout = Some((seconds * 1000.0) as u64);
+            }
+            "--url" => url = Some(value()?),
+            "-G" | "--get" => get = true,
+            "-I" | "--head" => head = true,
+            "-k" | "--insecure" => insecure = true,
+            option if IGNORED_FLAGS.contains(&option) => {}
+            option if IGNORED_OPTIONS.contains(&option) => {
+                value()?;
+            }
+            option if option.starts_with('-') && option.len() > 1 => {
+                return Err(anyhow::anyhow!("Unsupported curl option: {}", option));
+            }
+            _ => url = Some(option.to_string()),
+        }
+    }
+
+    let url = url.ok_or_else(|| anyhow::anyhow!("No URL given"))?;
+    let url = match url.contains("://") {
+        true => url,
+        false => format!("http://{}", url),
+    };
+    let url = url.split_once('#').map_or(url.as_str(), |(url, _)| url);
+    let (url, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut params = parse_query(query);
+
+    let data = (!data.is_empty()).then(|| data.join("&"));
+    let body = match (data, get) {
+        (Some(data), true) => {
+            params.extend(parse_query(&data));
+            None
+        }
+        (data, _) => data,
+    };
+    if body.is_some() {
+        set_default_header(
+            &mut headers,
+            "Content-Type",
+            "application/x-www-form-urlencoded",
+        );
+    }
+
+    let method = match method {
+        Some(method) => method.parse::<PyMethod>()?,
+        None if head => PyMethod::Head,
+        None if body.is_some() => PyMethod::Post,
+        None => PyMethod::Get,
+    };
+
+    let path = url
+        .split_once("://")
+        .and_then(|(_, rest)| rest.split_once('/'))
+        .map_or("", |(_, path)| path);
+    let name = identifier(&format!("{} {}", method, path));
+
+    Ok(GeneratedDefinition {
+        name,
+        method: method.to_string(),
+        url: url.into(),
+        headers: headers.into_iter().map(|(k, v)| (k, v.into())).collect(),
+        params: params.into_iter().map(|(k, v)| (k, v.into())).collect(),
+        body: body.map(Into::into),
+        timeout,
+        insecure,
+        ..Default::default()
+    })
+}
+
+/// Splits bundled short options, `-sSL` into `-s -S -L` and `-XPOST` into `-X POST`.
+fn expand_short_options(args: &[String]) -> Vec<String> {
+    let mut expanded = Vec::new();
+
+    for arg in args {
+        if arg.starts_with("--") || !arg.starts_with('-') || arg.len() <= 2 {
+            expanded.push(arg.clone());
+            continue;
+        }
+
+        for (i, c) in arg.char_indices().skip(1) {
+            expanded.push(format!("-{}", c));
+            if SHORT_OPTIONS_WITH_VALUE.contains(c) {
+                let value = &arg[i + c.len_utf8()..];
+                if !value.is_empty() {
+                    expanded.push(value.to_string());
+                }
+                break;
+            }
+        }
+    }
+
+    expanded
+}
+
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect()
+}
+
+/// Encodes a `--data-urlencode` value, `name=content` or `content`.
+fn urlencode_data(value: &str) -> Result<String> {
+    // `@file` and `name@file` read a file, an `@` after `=` is content
+    let reads_file = match (value.find('@'), value.find('=')) {
+        (Some(at), Some(eq)) => at < eq,
+        (at, _) => at.is_some(),
+    };
+    if reads_file {
+        return Err(anyhow::anyhow!(
+            "Reading data from a file is not supported: {}",
+            value
+        ));
+    }
+
+    let encode = |text: &str| url::form_urlencoded::byte_serialize(text.as_bytes()).collect();
+    Ok(match value.split_once('=') {
+        Some((name, content)) if !name.is_empty() => format!("{}={}", name, encode(content)),
+        Some((_, content)) => encode(content),
+        None => encode(value),
+    })
+}
+
+/// Splits text into commands at unquoted line breaks, and each command into words like a
+/// shell.
+fn split_commands(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut commands = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' | ';' => {
+                words.extend(word.take());
+                if !words.is_empty() {
+                    commands.push(std::mem::take(&mut words));
+                }
+            }
+            c if c.is_whitespace() => words.extend(word.take()),
+            '#' if word.is_none() => while chars.next_if(|&c| c != '\n').is_some() {},
+            '\\' => match chars.next() {
+                // Line continuation, also with Windows line endings
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some('\n') => {}
+                Some(c) => word.get_or_insert_default().push(c),
+                None => {}
+            },
+            '\'' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow::anyhow!("Unterminated ' quote")),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_default();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(anyhow::anyhow!("Unterminated \" quote")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow::anyhow!("Unterminated \" quote")),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                ansi_c_quoted(&mut chars, word.get_or_insert_default())?;
+            }
+            c => word.get_or_insert_default().push(c),
+        }
+    }
+
+    words.extend(word);
+    if !words.is_empty() {
+        commands.push(words);
+    }
+
+    Ok(commands)
+}
+
+/// Reads the rest of a `$'...'` string, resolving its escapes.
+fn ansi_c_quoted(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    word: &mut String,
+) -> Result<()> {
+    let unterminated = || anyhow::anyhow!("Unterminated $' quote");
+    let hex = |chars: &mut std::iter::Peekable<std::str::Chars<'_>>, max: usize| {
+        let mut code = String::new();
+        while code.len() < max
+            && let Some(c) = chars.next_if(|c| c.is_ascii_hexdigit())
+        {
+            code.push(c);
+        }
+        u32::from_str_radix(&code, 16).ok().and_then(char::from_u32)
+    };
+
+    loop {
+        match chars.next().ok_or_else(unterminated)? {
+            '\'' => return Ok(()),
+            '\\' => match chars.next().ok_or_else(unterminated)? {
+                'n' => word.push('\n'),
+                'r' => word.push('\r'),
+                't' => word.push('\t'),
+                'x' => word.extend(hex(chars, 2)),
+                'u' => word.extend(hex(chars, 4)),
+                'U' => word.extend(hex(chars, 8)),
+                c => word.push(c),
+            },
+            c => word.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importer::Template;
+
+    fn command(text: &str) -> Result<GeneratedDefinition> {
+        let mut definitions = parse(text)?;
+        assert_eq!(definitions.len(), 1);
+        Ok(definitions.remove(0))
+    }
+
+    fn headers(definition: &GeneratedDefinition) -> Vec<(&str, Template)> {
+        definition
+            .headers
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn quoting() {
+        let commands =
+            split_commands(r#"curl 'a b' "c \"d\" \$e" f\ g $'h\n\x41' 'i'"j"k"#).unwrap();
+        assert_eq!(
+            commands,
+            vec![vec!["curl", "a b", r#"c "d" $e"#, "f g", "h\nA", "ijk"]]
+        );
+
+        assert!(split_commands("curl 'unterminated").is_err());
+        assert!(split_commands(r#"curl "unterminated"#).is_err());
+    }
+
+    #[test]
+    fn line_continuations() {
+        let commands =
+            split_commands("curl https://example.com \\\n  -H 'A: 1' \\\r\n  -k\n").unwrap();
+        assert_eq!(
+            commands,
+            vec![vec!["curl", "https://example.com", "-H", "A: 1", "-k"]]
+        );
+    }
+
+    #[test]
+    fn several_commands() {
+        let definitions = parse(
+            "# users\ncurl https://example.com/users\n\necho skipped\ncurl -X DELETE https://example.com/users/1",
+        )
+        .unwrap();
+
+        let names: Vec<&str> = definitions.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["get_users", "delete_users_1"]);
+    }
+
+    #[test]
+    fn options() {
+        let definition = command(
+            "curl -sSL -XPUT 'https://example.com/users/1?page=2#top' -H 'Accept: text/plain' -m 1.5 -k",
+        )
+        .unwrap();
+
+        assert_eq!(definition.method, "PUT");
+        assert_eq!(definition.url, "https://example.com/users/1".into());
+        assert_eq!(definition.params, vec![("page".to_string(), "2".into())]);
+        assert_eq!(headers(&definition), vec![("Accept", "text/plain".into())]);
+        assert_eq!(definition.timeout, Some(1500));
+        assert!(definition.insecure);
+    }
+
+    #[test]
+    fn data() {
+        let definition = command("curl example.com --data-raw '@user' -d 'b=2'").unwrap();
+
+        assert_eq!(definition.method, "POST");
+        assert_eq!(definition.url, "http://example.com".into());
+        assert_eq!(definition.body, Some("@user&b=2".into()));
+        assert_eq!(
+            headers(&definition),
+            vec![("Content-Type", "application/x-www-form-urlencoded".into())]
+        );
+
+        let definition = command("curl -G example.com -d 'a=1' --data-urlencode 'b=x y'").unwrap();
+        assert_eq!(definition.method, "GET");
+        assert_eq!(definition.body, None);
+        assert_eq!(
+            definition.params,
+            vec![
+                ("a".to_string(), "1".into()),
+                ("b".to_string(), "x y".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let error = |text: &str| format!("{:#}", command(text).unwrap_err());
+
+        assert!(error("curl example.com -d @body.json").contains("Reading data from a file"));
+        assert!(error("curl example.com --data-urlencode a@b").contains("Reading data"));
+        assert!(command("curl example.com --data-urlencode a=@b").is_ok());
+        assert!(error("curl example.com -b cookies.txt").contains("Reading cookies"));
+        assert!(error("curl example.com --proxy p").contains("Unsupported curl option"));
+        assert!(error("curl -X TRACE example.com").contains("Unsupported method"));
+        assert!(error("curl -H").contains("Missing value for -H"));
+        assert!(error("curl -s").contains("No URL given"));
+    }
+}
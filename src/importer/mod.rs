@@ -0,0 +1,435 @@
+//! Generating definitions from requests described elsewhere, like curl commands.
+
+mod curl;
+mod openapi;
+mod postman;
+
+use crate::api::PyMethod;
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Where `ncurl import` reads requests from.
+#[derive(Subcommand, Clone, Debug, Eq, PartialEq)]
+pub enum ImportSource {
+    /// Import curl commands, e.g. copied from the network tab of browser devtools
+    Curl {
+        /// Name of the definition, derived from the method and URL by default
+        #[clap(long)]
+        name: Option<String>,
+
+        /// Read curl commands from a file, one per line
+        #[clap(long, conflicts_with = "command")]
+        from_file: Option<PathBuf>,
+
+        /// The curl command, quoted or as separate arguments
+        #[clap(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Import a Postman v2.1 collection, with folders as tags
+    Postman {
+        /// The exported collection
+        collection: PathBuf,
+
+        /// A Postman environment providing variables
+        #[clap(long)]
+        env: Option<PathBuf>,
+    },
+    /// Import the operations of an OpenAPI 3 document, in YAML or JSON
+    Openapi {
+        /// The OpenAPI document
+        spec: PathBuf,
+    },
+}
+
+/// Generates definitions from `source` and appends them to the script at `path`, creating it
+/// if needed. Returns the names of the new definitions.
+pub fn import(path: &Path, source: &ImportSource) -> Result<Vec<String>> {
+    let existing = match path.exists() {
+        true => {
+            std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?
+        }
+        false => String::new(),
+    };
+
+    let (preamble, definitions) = match source {
+        ImportSource::Curl {
+            name,
+            from_file,
+            command,
+        } => {
+            let text = match from_file {
+                Some(file) => std::fs::read_to_string(file)
+                    .context(format!("Failed to read {}", file.display()))?,
+                None if command.len() == 1 => command[0].clone(),
+                None => curl::join_args(command),
+            };
+
+            let mut definitions = curl::parse(&text)?;
+            if let (Some(name), Some(first)) = (name, definitions.first_mut()) {
+                first.name = identifier(name);
+            }
+            (None, definitions)
+        }
+        ImportSource::Postman { collection, env } => {
+            postman::import(collection, env.as_deref(), &existing)?
+        }
+        ImportSource::Openapi { spec } => openapi::import(spec, &existing)?,
+    };
+
+    if definitions.is_empty() {
+        return Err(anyhow::anyhow!("No requests found to import"));
+    }
+
+    append(path, &existing, preamble, definitions)
+}
+
+/// A request turned into a `@nc.define` function sending it with `client.send`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct GeneratedDefinition {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub doc: Option<String>,
+    /// Local variables assigned before sending, as names and Python expressions
+    pub variables: Vec<(String, String)>,
+    /// Name of the method constant, `GET`, `POST`, ...
+    pub method: String,
+    pub url: Template,
+    pub headers: Vec<(String, Template)>,
+    pub params: Vec<(String, Template)>,
+    pub body: Option<Template>,
+    /// Timeout in milliseconds
+    pub timeout: Option<u64>,
+    /// Accept invalid certificates
+    pub insecure: bool,
+    /// Comments about parts of the source that could not be carried over
+    pub notes: Vec<String>,
+    /// Statements checking the response
+    pub assertions: Vec<String>,
+}
+
+impl GeneratedDefinition {
+    /// Renders the definition as Python, expecting neocurl imported as `nc`.
+    pub fn render(&self) -> String {
+        let mut code = match self.tags.is_empty() {
+            true => String::from("@nc.define\n"),
+            false => {
+                let tags: Vec<String> = self.tags.iter().map(|t| python_str(t)).collect();
+                format!("@nc.define(tags=[{}])\n", tags.join(", "))
+            }
+        };
+        code.push_str(&format!("def {}(client):\n", self.name));
+        if let Some(doc) = &self.doc {
+            let doc = doc
+                .trim()
+                .replace('\\', "\\\\")
+                .replace("\"\"\"", "\\\"\"\"");
+            code.push_str(&format!(
+                "    \"\"\"{}\"\"\"\n",
+                doc.replace('\n', "\n    ")
+            ));
+        }
+        for note in &self.notes {
+            code.push_str(&format!("    # {}\n", note));
+        }
+
+        for (name, value) in &self.variables {
+            code.push_str(&format!("    {} = {}\n", name, value));
+        }
+
+        code.push_str("    response = client.send(\n");
+        code.push_str(&format!("        {},\n", self.url.render()));
+        code.push_str(&format!("        method=nc.{},\n", self.method));
+        if !self.headers.is_empty() {
+            code.push_str(&format!(
+                "        headers={},\n",
+                python_dict(&self.headers)
+            ));
+        }
+        if !self.params.is_empty() {
+            code.push_str(&format!("        params={},\n", python_dict(&self.params)));
+        }
+        if let Some(body) = &self.body {
+            code.push_str(&format!("        body={},\n", body.render()));
+        }
+        if let Some(timeout) = self.timeout {
+            code.push_str(&format!("        timeout={},\n", timeout));
+        }
+        if self.insecure {
+            code.push_str("        insecure=True,\n");
+        }
+        code.push_str("    )\n");
+        code.push_str("    response.print()\n");
+        for assertion in &self.assertions {
+            code.push_str(&format!("    {}\n", assertion));
+        }
+
+        code
+    }
+}
+
+/// Appends the preamble and definitions to the script, renaming definitions whose name is
+/// already taken.
+fn append(
+    path: &Path,
+    existing: &str,
+    preamble: Option<String>,
+    mut definitions: Vec<GeneratedDefinition>,
+) -> Result<Vec<String>> {
+    let mut taken: Vec<String> = regex::Regex::new(r"(?m)^def\s+(\w+)\s*\(")
+        .expect("Valid regex")
+        .captures_iter(existing)
+        .map(|c| c[1].to_string())
+        .collect();
+    for definition in &mut definitions {
+        let base = definition.name.clone();
+        let mut i = 2;
+        while taken.contains(&definition.name) {
+            definition.name = format!("{}_{}", base, i);
+            i += 1;
+        }
+        taken.push(definition.name.clone());
+    }
+
+    let mut code = existing.trim_end().to_string();
+    if !existing.contains("import neocurl as nc") {
+        if !code.is_empty() {
+            code.push_str("\n\n");
+        }
+        code.push_str("import neocurl as nc");
+    }
+    if let Some(preamble) = preamble {
+        code.push_str("\n\n");
+        code.push_str(preamble.trim_end());
+    }
+    for definition in &definitions {
+        code.push_str("\n\n");
+        code.push_str(&definition.render());
+        code = code.trim_end().to_string();
+    }
+    code.push('\n');
+
+    std::fs::write(path, code).context(format!("Failed to write {}", path.display()))?;
+
+    Ok(definitions.into_iter().map(|d| d.name).collect())
+}
+
+/// Turns text into a Python identifier in snake case, also splitting camel case words.
+pub fn identifier(text: &str) -> String {
+    let mut name = String::new();
+    let mut previous = ' ';
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase()
+                && (previous.is_ascii_lowercase() || previous.is_ascii_digit())
+                && !name.ends_with('_')
+            {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        } else if !name.ends_with('_') {
+            name.push('_');
+        }
+        previous = c;
+    }
+    let mut name = name.trim_matches('_').to_string();
+
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name = format!("request_{}", name)
+            .trim_end_matches('_')
+            .to_string();
+    }
+    if PYTHON_KEYWORDS.contains(&name.as_str()) {
+        name.push('_');
+    }
+
+    name
+}
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif",
+    "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+    "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+];
+
+/// Renders a Python string literal.
+pub fn python_str(text: &str) -> String {
+    format!("\"{}\"", escape(text, '"'))
+}
+
+/// Escapes text for a Python string literal quoted with `quote`.
+fn escape(text: &str, quote: char) -> String {
+    let mut literal = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            c if c == quote => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c if c.is_control() => literal.push_str(&format!("\\x{:02x}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+
+    literal
+}
+
+/// Renders a dict of strings, one item per line.
+fn python_dict(items: &[(String, Template)]) -> String {
+    let mut dict = String::from("{\n");
+    for (key, value) in items {
+        dict.push_str(&format!(
+            "            {}: {},\n",
+            python_str(key),
+            value.render()
+        ));
+    }
+    dict.push_str("        }");
+
+    dict
+}
+
+/// Whether neocurl can send requests with the method, in any case.
+fn is_supported(method: &str) -> bool {
+    method.parse::<PyMethod>().is_ok()
+}
+
+/// Text of a JSON value, strings without quotes and `null` as nothing.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Adds a header unless one with the same name is already set.
+fn set_default_header<T>(headers: &mut Vec<(String, T)>, key: &str, value: impl Into<T>) {
+    if !headers.iter().any(|(k, _)| k.eq_ignore_ascii_case(key)) {
+        headers.push((key.to_string(), value.into()));
+    }
+}
+
+/// A string of generated code, with parts computed when the script runs.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Template(Vec<Part>);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Part {
+    Text(String),
+    /// A Python expression evaluating to a string
+    Code(String),
+}
+
+impl Template {
+    pub fn push_text(&mut self, text: &str) {
+        match self.0.last_mut() {
+            _ if text.is_empty() => {}
+            Some(Part::Text(last)) => last.push_str(text),
+            _ => self.0.push(Part::Text(text.to_string())),
+        }
+    }
+
+    pub fn push_code(&mut self, code: impl Into<String>) {
+        self.0.push(Part::Code(code.into()));
+    }
+
+    /// Appends the parts of another template.
+    pub fn extend(&mut self, other: Template) {
+        for part in other.0 {
+            match part {
+                Part::Text(text) => self.push_text(&text),
+                Part::Code(code) => self.push_code(code),
+            }
+        }
+    }
+
+    /// Whether the template has no code parts, its text is then known already.
+    pub fn is_text(&self) -> bool {
+        self.0.iter().all(|part| matches!(part, Part::Text(_)))
+    }
+
+    /// Text of a template without code parts.
+    pub fn text(&self) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) | Part::Code(text) => text.as_str(),
+            })
+            .collect()
+    }
+
+    /// Renders a string literal, or an f-string if the template has code parts.
+    pub fn render(&self) -> String {
+        if self.is_text() {
+            return python_str(&self.text());
+        }
+        if let [Part::Code(code)] = self.0.as_slice() {
+            return code.clone();
+        }
+        // Expressions in f-strings cannot contain backslashes before Python 3.12
+        if self
+            .0
+            .iter()
+            .any(|part| matches!(part, Part::Code(code) if code.contains(['\\', '"'])))
+        {
+            let parts: Vec<String> = self
+                .0
+                .iter()
+                .map(|part| match part {
+                    Part::Text(text) => python_str(text),
+                    Part::Code(code) => code.clone(),
+                })
+                .collect();
+            return parts.join(" + ");
+        }
+
+        let mut literal = String::from("f\"");
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => {
+                    literal.push_str(&escape(text, '"').replace('{', "{{").replace('}', "}}"))
+                }
+                Part::Code(code) => literal.push_str(&format!("{{{}}}", code)),
+            }
+        }
+        literal.push('"');
+
+        literal
+    }
+
+    /// Renders an expression concatenating the parts, for use in a code part.
+    pub fn render_concat(&self) -> String {
+        if self.0.is_empty() {
+            return "''".to_string();
+        }
+
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => format!("'{}'", escape(text, '\'')),
+                Part::Code(code) => code.clone(),
+            })
+            .collect();
+
+        parts.join(" + ")
+    }
+}
+
+impl From<&str> for Template {
+    fn from(text: &str) -> Self {
+        Template(vec![Part::Text(text.to_string())])
+    }
+}
+
+impl From<String> for Template {
+    fn from(text: String) -> Self {
+        Template(vec![Part::Text(text)])
+    }
+}
@@ -0,0 +1,688 @@
+//! Generating definitions from the operations of OpenAPI 3 documents.
+
+use super::{GeneratedDefinition, Template, identifier, is_supported, python_str, text};
+use crate::openapi::{Operation, Spec};
+use anyhow::Result;
+use serde_json::{Map, Value};
+use std::path::Path;
+
+/// Server used if the document lists none.
+const DEFAULT_SERVER: &str = "http://localhost";
+
+/// Names generated definitions use themselves, path parameters are renamed to not shadow them.
+const RESERVED_NAMES: &[&str] = &["client", "response", "nc", "base64"];
+
+/// Reads the operations of a document into definitions.
+///
+/// Also returns the code defining `BASE_URL`, unless the `existing` script defines it already.
+pub fn import(path: &Path, existing: &str) -> Result<(Option<String>, Vec<GeneratedDefinition>)> {
+    let spec = Spec::load(path)?;
+
+    let mut definitions = Vec::new();
+    let mut uses_base64 = false;
+    for operation in spec.operations() {
+        if !is_supported(operation.method) {
+            tracing::warn!(
+                "Skipped {} {}, its method is not supported",
+                operation.method,
+                operation.path
+            );
+            continue;
+        }
+
+        let definition = definition(&spec, &operation);
+        uses_base64 |= definition
+            .headers
+            .iter()
+            .any(|(_, value)| value.render().contains("base64."));
+        definitions.push(definition);
+    }
+
+    let mut preamble = Vec::new();
+    if uses_base64 && !existing.contains("import base64") {
+        preamble.push("import base64".to_string());
+    }
+    if !existing.contains("BASE_URL = ") {
+        let server = spec.server_url().unwrap_or_default();
+        // Relative servers are relative to where the document is served
+        let server = match server.contains("://") {
+            true => server,
+            false => format!("{}{}", DEFAULT_SERVER, server),
+        };
+        preamble.push(format!(
+            "# Base URL of the API, BASE_URL in the environment takes precedence\n\
+            BASE_URL = nc.env(\"BASE_URL\") or {}",
+            python_str(&server)
+        ));
+    }
+
+    let preamble = (!preamble.is_empty()).then(|| preamble.join("\n\n") + "\n");
+
+    Ok((preamble, definitions))
+}
+
+fn definition(spec: &Spec, operation: &Operation<'_>) -> GeneratedDefinition {
+    let value = operation.value;
+    let mut definition = GeneratedDefinition {
+        name: match value["operationId"].as_str() {
+            Some(id) => identifier(id),
+            None => identifier(&format!("{} {}", operation.method, operation.path)),
+        },
+        tags: value["tags"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|tag| tag.as_str().map(str::to_string))
+            .collect(),
+        doc: value["summary"]
+            .as_str()
+            .or(value["description"].as_str())
+            .map(str::to_string),
+        method: operation.method.to_string(),
+        ..Default::default()
+    };
+    if value["deprecated"].as_bool() == Some(true) {
+        definition
+            .notes
+            .push("The operation is deprecated".to_string());
+    }
+
+    let mut cookies = Template::default();
+    let mut path_values = Vec::new();
+    for parameter in &operation.parameters {
+        let name = parameter["name"].as_str().unwrap_or_default();
+        let location = parameter["in"].as_str().unwrap_or_default();
+        let sample = text(&parameter_sample(spec, parameter));
+
+        match location {
+            "path" => {
+                let mut variable = identifier(name);
+                if RESERVED_NAMES.contains(&variable.as_str()) {
+                    variable.push('_');
+                }
+                definition
+                    .variables
+                    .push((variable.clone(), python_str(&sample)));
+                path_values.push((name, variable));
+            }
+            _ if parameter["required"].as_bool() != Some(true) => {}
+            "query" => definition.params.push((name.to_string(), sample.into())),
+            // Set by neocurl from the other fields of the request instead
+            "header"
+                if ["accept", "content-type", "authorization"]
+                    .contains(&name.to_lowercase().as_str()) => {}
+            "header" => definition.headers.push((name.to_string(), sample.into())),
+            "cookie" => add_cookie(&mut cookies, name, sample.into()),
+            _ => {}
+        }
+    }
+
+    let mut url = Template::default();
+    url.push_code("BASE_URL");
+    let mut rest = operation.path;
+    while let Some(start) = rest.find('{') {
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 1..start + length];
+        url.push_text(&rest[..start]);
+        match path_values.iter().find(|(n, _)| *n == name) {
+            Some((_, variable)) => url.push_code(variable.clone()),
+            // Undeclared path parameters are kept, so the request visibly fails
+            None => url.push_text(&rest[start..start + length + 1]),
+        }
+        rest = &rest[start + length + 1..];
+    }
+    url.push_text(rest);
+    definition.url = url;
+
+    read_body(spec, value, &mut definition);
+    add_security(spec, value, &mut definition, &mut cookies);
+    if cookies != Template::default() {
+        definition.headers.push(("Cookie".to_string(), cookies));
+    }
+
+    definition.assertions.extend(status_assertion(value));
+
+    definition
+}
+
+/// Sets an example body for the request body of the operation.
+fn read_body(spec: &Spec, operation: &Value, definition: &mut GeneratedDefinition) {
+    let body = spec.resolve(&operation["requestBody"]);
+    let Some(content) = body["content"].as_object() else {
+        return;
+    };
+
+    let json = content
+        .iter()
+        .find(|(media, _)| *media == "application/json" || media.ends_with("+json"));
+    let form = content.get_key_value("application/x-www-form-urlencoded");
+    let text = content.iter().find(|(media, _)| media.starts_with("text/"));
+
+    let Some((media, value)) = json.or(form).or(text) else {
+        if let Some(media) = content.keys().next() {
+            definition.notes.push(format!(
+                "The {} request body was not generated, add it by hand",
+                media
+            ));
+        }
+        return;
+    };
+
+    let sample = media_sample(spec, value);
+    let body = match (json.is_some(), form.is_some(), sample) {
+        (true, _, sample) => sample.to_string(),
+        (_, true, Value::Object(fields)) => {
+            let mut form = url::form_urlencoded::Serializer::new(String::new());
+            for (key, value) in &fields {
+                form.append_pair(key, &super::text(value));
+            }
+            form.finish()
+        }
+        (_, _, sample) => super::text(&sample),
+    };
+
+    definition
+        .headers
+        .push(("Content-Type".to_string(), media.as_str().into()));
+    definition.body = Some(body.into());
+}
+
+/// Adds the headers or query parameters of the first security requirement, reading the
+/// credentials from an environment variable named after the scheme.
+fn add_security(
+    spec: &Spec,
+    operation: &Value,
+    definition: &mut GeneratedDefinition,
+    cookies: &mut Template,
+) {
+    let requirements = match &operation["security"] {
+        Value::Array(requirements) => requirements,
+        _ => match &spec.document()["security"] {
+            Value::Array(requirements) => requirements,
+            _ => return,
+        },
+    };
+    // An empty requirement makes security optional
+    let Some(requirement) = requirements.first().and_then(Value::as_object) else {
+        return;
+    };
+
+    for name in requirement.keys() {
+        let scheme = spec.resolve(&spec.document()["components"]["securitySchemes"][name]);
+        let env = identifier(name).to_uppercase();
+        let credentials = format!("(nc.env('{}') or '')", env);
+
+        let kind = scheme["type"].as_str().unwrap_or_default();
+        let http = scheme["scheme"].as_str().unwrap_or_default().to_lowercase();
+        match (kind, http.as_str()) {
+            ("http", "basic") => {
+                let mut header = Template::from("Basic ");
+                header.push_code(format!(
+                    "base64.b64encode({}.encode()).decode()",
+                    credentials
+                ));
+                definition
+                    .headers
+                    .push(("Authorization".to_string(), header));
+            }
+            ("http", "bearer") | ("oauth2", _) | ("openIdConnect", _) => {
+                let mut header = Template::from("Bearer ");
+                header.push_code(credentials);
+                definition
+                    .headers
+                    .push(("Authorization".to_string(), header));
+            }
+            ("apiKey", _) => {
+                let key = scheme["name"].as_str().unwrap_or_default().to_string();
+                let mut value = Template::default();
+                value.push_code(credentials);
+                match scheme["in"].as_str() {
+                    Some("query") => definition.params.push((key, value)),
+                    Some("cookie") => add_cookie(cookies, &key, value),
+                    _ => definition.headers.push((key, value)),
+                }
+            }
+            (kind, _) => definition.notes.push(format!(
+                "The {} security scheme `{}` was not generated, add it by hand",
+                kind, name
+            )),
+        }
+    }
+}
+
+fn add_cookie(cookies: &mut Template, name: &str, value: Template) {
+    if *cookies != Template::default() {
+        cookies.push_text("; ");
+    }
+    cookies.push_text(&format!("{}=", name));
+    cookies.extend(value);
+}
+
+/// Asserts that the status code is one of the documented successful ones.
+fn status_assertion(operation: &Value) -> Option<String> {
+    let responses = operation["responses"].as_object()?;
+    let codes: Vec<&str> = responses
+        .keys()
+        .map(String::as_str)
+        .filter(|code| code.starts_with(['2', '3']) && code.parse::<u16>().is_ok())
+        .collect();
+    let got = "but got {response.status_code} ({response.status})";
+
+    match codes.as_slice() {
+        [] if responses.contains_key("2XX") => Some(format!(
+            "assert 200 <= response.status_code < 300, f\"Expected a 2XX status code, {}\"",
+            got
+        )),
+        [] => None,
+        [code] => Some(format!(
+            "assert response.status_code == {}, f\"Expected status code {}, {}\"",
+            code, code, got
+        )),
+        codes => Some(format!(
+            "assert response.status_code in ({}), f\"Expected status code {}, {}\"",
+            codes.join(", "),
+            codes.join(" or "),
+            got
+        )),
+    }
+}
+
+/// Example of a parameter, from its examples or its schema.
+fn parameter_sample(spec: &Spec, parameter: &Value) -> Value {
+    if let Some(example) = parameter.get("example") {
+        return example.clone();
+    }
+    if let Some(example) = first_example(spec, &parameter["examples"]) {
+        return example;
+    }
+
+    sample(spec, &parameter["schema"], &mut Vec::new(), false).unwrap_or_default()
+}
+
+/// Example of a media type, from its examples or its schema.
+fn media_sample(spec: &Spec, media: &Value) -> Value {
+    if let Some(example) = media.get("example") {
+        return example.clone();
+    }
+    if let Some(example) = first_example(spec, &media["examples"]) {
+        return example;
+    }
+
+    sample(spec, &media["schema"], &mut Vec::new(), true).unwrap_or_default()
+}
+
+/// Value of the first entry of an `examples` map.
+fn first_example(spec: &Spec, examples: &Value) -> Option<Value> {
+    let (_, example) = examples.as_object()?.iter().next()?;
+    spec.resolve(example).get("value").cloned()
+}
+
+/// Generates a value matching a schema, preferring its examples and defaults.
+///
+/// `refs` are the references being sampled, a schema referring to itself is left out the second
+/// time by returning `None`. Read-only properties are left out of request bodies.
+fn sample(spec: &Spec, schema: &Value, refs: &mut Vec<String>, request: bool) -> Option<Value> {
+    let reference = schema["$ref"].as_str().map(str::to_string);
+    if let Some(reference) = &reference {
+        if refs.contains(reference) {
+            return None;
+        }
+        refs.push(reference.clone());
+    }
+
+    let value = sample_resolved(spec, spec.resolve(schema), refs, request);
+
+    if reference.is_some() {
+        refs.pop();
+    }
+
+    Some(value)
+}
+
+fn sample_resolved(spec: &Spec, schema: &Value, refs: &mut Vec<String>, request: bool) -> Value {
+    for key in ["example", "default", "const"] {
+        if let Some(value) = schema.get(key) {
+            return value.clone();
+        }
+    }
+    for key in ["examples", "enum"] {
+        if let Some(value) = schema[key].as_array().and_then(|values| values.first()) {
+            return value.clone();
+        }
+    }
+
+    if let Some(schemas) = schema["allOf"].as_array() {
+        let mut merged = Map::new();
+        for schema in schemas {
+            match sample(spec, schema, refs, request) {
+                Some(Value::Object(fields)) => merged.extend(fields),
+                Some(value) if merged.is_empty() => return value,
+                _ => {}
+            }
+        }
+        return Value::Object(merged);
+    }
+    for key in ["oneOf", "anyOf"] {
+        if let Some(first) = schema[key].as_array().and_then(|schemas| schemas.first()) {
+            return sample(spec, first, refs, request).unwrap_or_default();
+        }
+    }
+
+    let kind = match &schema["type"] {
+        Value::String(kind) => kind.as_str(),
+        // OpenAPI 3.1 allows several types, like `[string, "null"]`
+        Value::Array(kinds) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|kind| *kind != "null")
+            .unwrap_or("null"),
+        _ if schema.get("properties").is_some() => "object",
+        _ if schema.get("items").is_some() => "array",
+        _ => "string",
+    };
+
+    match kind {
+        "object" => {
+            let mut fields = Map::new();
+            for (name, property) in schema["properties"].as_object().into_iter().flatten() {
+                if request && spec.resolve(property)["readOnly"].as_bool() == Some(true) {
+                    continue;
+                }
+                if let Some(value) = sample(spec, property, refs, request) {
+                    fields.insert(name.clone(), value);
+                }
+            }
+            Value::Object(fields)
+        }
+        "array" => Value::Array(
+            sample(spec, &schema["items"], refs, request)
+                .into_iter()
+                .collect(),
+        ),
+        "integer" => Value::from(schema["minimum"].as_i64().unwrap_or(0).max(0)),
+        "number" => Value::from(schema["minimum"].as_f64().unwrap_or(0.0).max(0.0)),
+        "boolean" => Value::Bool(true),
+        "null" => Value::Null,
+        _ => Value::from(match schema["format"].as_str().unwrap_or_default() {
+            "date-time" => "2024-01-01T00:00:00Z",
+            "date" => "2024-01-01",
+            "time" => "12:00:00",
+            "email" => "user@example.com",
+            "uuid" => "00000000-0000-0000-0000-000000000000",
+            "uri" | "url" => "https://example.com",
+            "hostname" => "example.com",
+            "ipv4" => "127.0.0.1",
+            "ipv6" => "::1",
+            "byte" => "c3RyaW5n",
+            _ => "string",
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definitions(spec: &str) -> Vec<GeneratedDefinition> {
+        let spec = Spec::parse(spec).unwrap();
+        spec.operations()
+            .iter()
+            .map(|operation| definition(&spec, operation))
+            .collect()
+    }
+
+    fn headers(definition: &GeneratedDefinition) -> Vec<(&str, String)> {
+        definition
+            .headers
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.render()))
+            .collect()
+    }
+
+    #[test]
+    fn body_samples() {
+        let definitions = definitions(
+            r##"
+openapi: 3.0.3
+paths:
+  /users:
+    post:
+      requestBody:
+        content:
+          application/json:
+            schema: { $ref: "#/components/schemas/User" }
+      responses:
+        201:
+          content:
+            application/json:
+              schema: { $ref: "#/components/schemas/User" }
+components:
+  schemas:
+    User:
+      type: object
+      properties:
+        id: { type: integer, readOnly: true }
+        name: { type: string, example: neo }
+        email: { type: string, format: email }
+        age: { type: integer, minimum: 18 }
+        role: { type: string, enum: [admin, user] }
+        manager: { $ref: "#/components/schemas/User" }
+        friends:
+          type: array
+          items: { $ref: "#/components/schemas/User" }
+"##,
+        );
+
+        assert_eq!(
+            definitions[0].body.as_ref().unwrap().render(),
+            python_str(
+                r#"{"name":"neo","email":"user@example.com","age":18,"role":"admin","friends":[]}"#
+            )
+        );
+        assert_eq!(
+            headers(&definitions[0]),
+            [("Content-Type", "\"application/json\"".to_string())]
+        );
+    }
+
+    #[test]
+    fn form_body() {
+        let definitions = definitions(
+            r#"
+openapi: 3.1.0
+paths:
+  /login:
+    post:
+      requestBody:
+        content:
+          application/x-www-form-urlencoded:
+            schema:
+              type: object
+              properties:
+                user: { type: string, example: "neo smith" }
+                remember: { type: [boolean, "null"] }
+      responses:
+        204: { description: Logged in }
+"#,
+        );
+
+        assert_eq!(
+            definitions[0].body.as_ref().unwrap().render(),
+            "\"user=neo+smith&remember=true\""
+        );
+    }
+
+    #[test]
+    fn status_assertions() {
+        let definitions = definitions(
+            r#"
+openapi: 3.0.3
+paths:
+  /a:
+    post:
+      responses:
+        201: { description: Created }
+        400: { description: Invalid }
+    get:
+      responses:
+        200: { description: OK }
+        304: { description: Not modified }
+    put:
+      responses:
+        2XX: { description: OK }
+    delete:
+      responses:
+        default: { description: Anything }
+"#,
+        );
+        let got = "but got {response.status_code} ({response.status})";
+
+        // Operations are in the order of the methods, not of the document
+        let assertions: Vec<&[String]> = definitions.iter().map(|d| &d.assertions[..]).collect();
+        assert_eq!(
+            assertions,
+            [
+                &[format!(
+                    "assert response.status_code in (200, 304), f\"Expected status code 200 or 304, {}\"",
+                    got
+                )][..],
+                &[format!(
+                    "assert 200 <= response.status_code < 300, f\"Expected a 2XX status code, {}\"",
+                    got
+                )],
+                &[format!(
+                    "assert response.status_code == 201, f\"Expected status code 201, {}\"",
+                    got
+                )],
+                &[],
+            ]
+        );
+    }
+
+    #[test]
+    fn path_parameters() {
+        let definitions = definitions(
+            r#"
+openapi: 3.0.3
+paths:
+  /clients/{client}/items/{item-id}/{other}:
+    parameters:
+      - { name: client, in: path, required: true, schema: { type: integer } }
+    get:
+      parameters:
+        - { name: item-id, in: path, required: true, example: abc }
+        - { name: page, in: query, required: true, schema: { type: integer, default: 1 } }
+        - { name: sort, in: query, schema: { type: string } }
+        - { name: X-Request-Id, in: header, required: true, schema: { type: string, format: uuid } }
+        - { name: Accept, in: header, required: true, schema: { type: string } }
+        - { name: session, in: cookie, required: true, example: s1 }
+      responses:
+        200: { description: OK }
+"#,
+        );
+        let definition = &definitions[0];
+
+        assert_eq!(definition.name, "get_clients_client_items_item_id_other");
+        assert_eq!(
+            definition.variables,
+            [
+                ("client_".to_string(), "\"0\"".to_string()),
+                ("item_id".to_string(), "\"abc\"".to_string())
+            ]
+        );
+        // Undeclared path parameters are kept as they are
+        assert_eq!(
+            definition.url.render(),
+            "f\"{BASE_URL}/clients/{client_}/items/{item_id}/{{other}}\""
+        );
+        assert_eq!(definition.params, [("page".to_string(), "1".into())]);
+        assert_eq!(
+            headers(definition),
+            [
+                (
+                    "X-Request-Id",
+                    "\"00000000-0000-0000-0000-000000000000\"".to_string()
+                ),
+                ("Cookie", "\"session=s1\"".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn security_schemes() {
+        let definitions = definitions(
+            r#"
+openapi: 3.0.3
+security:
+  - bearerAuth: []
+paths:
+  /bearer:
+    get:
+      responses: {}
+  /basic:
+    get:
+      security:
+        - basic: []
+      responses: {}
+  /keys:
+    get:
+      security:
+        - headerKey: []
+          queryKey: []
+          cookieKey: []
+      responses: {}
+  /public:
+    get:
+      security: [{}]
+      responses: {}
+  /mutual:
+    get:
+      security:
+        - mutual: []
+      responses: {}
+components:
+  securitySchemes:
+    bearerAuth: { type: http, scheme: bearer }
+    basic: { type: http, scheme: Basic }
+    headerKey: { type: apiKey, in: header, name: X-API-Key }
+    queryKey: { type: apiKey, in: query, name: api_key }
+    cookieKey: { type: apiKey, in: cookie, name: sid }
+    mutual: { type: mutualTLS }
+"#,
+        );
+
+        assert_eq!(
+            headers(&definitions[0]),
+            [(
+                "Authorization",
+                "f\"Bearer {(nc.env('BEARER_AUTH') or '')}\"".to_string()
+            )]
+        );
+        assert_eq!(
+            headers(&definitions[1]),
+            [(
+                "Authorization",
+                "f\"Basic {base64.b64encode((nc.env('BASIC') or '').encode()).decode()}\""
+                    .to_string()
+            )]
+        );
+        assert_eq!(
+            headers(&definitions[2]),
+            [
+                ("X-API-Key", "(nc.env('HEADER_KEY') or '')".to_string()),
+                (
+                    "Cookie",
+                    "f\"sid={(nc.env('COOKIE_KEY') or '')}\"".to_string()
+                )
+            ]
+        );
+        assert_eq!(definitions[2].params[0].0, "api_key");
+        assert!(definitions[3].headers.is_empty());
+        assert_eq!(
+            definitions[4].notes,
+            ["The mutualTLS security scheme `mutual` was not generated, add it by hand"]
+        );
+    }
+}
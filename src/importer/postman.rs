@@ -0,0 +1,753 @@
+//! Reading Postman v2.1 collections and environments.
+
+use super::{
+    GeneratedDefinition, Template, identifier, is_supported, python_str, set_default_header, text,
+};
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde_json::Value;
+use std::path::Path;
+
+/// Defines the `postman_var` function generated definitions look variables up with.
+const POSTMAN_VAR: &str = r#"def postman_var(name):
+    """Value of a Postman variable, from the environment or the collection."""
+    dynamic = {
+        "$guid": lambda: str(uuid.uuid4()),
+        "$randomUUID": lambda: str(uuid.uuid4()),
+        "$timestamp": lambda: str(int(time.time())),
+        "$isoTimestamp": lambda: time.strftime("%Y-%m-%dT%H:%M:%SZ", time.gmtime()),
+        "$randomInt": lambda: str(random.randint(0, 1000)),
+    }
+    if name in dynamic:
+        return dynamic[name]()
+
+    value = nc.env(name)
+    if value is not None:
+        return value
+    return POSTMAN_VARIABLES.get(name, "")
+"#;
+
+/// Reads a collection, and optionally an environment, into definitions.
+///
+/// Also returns the code defining the variables, or adding to them if an earlier import
+/// defined them in the `existing` script already.
+pub fn import(
+    collection: &Path,
+    env: Option<&Path>,
+    existing: &str,
+) -> Result<(Option<String>, Vec<GeneratedDefinition>)> {
+    let collection = read_json(collection)?;
+
+    let schema = collection["info"]["schema"].as_str().unwrap_or_default();
+    if !schema.contains("/v2.") {
+        return Err(anyhow::anyhow!(
+            "Only Postman v2 collections are supported, export the collection as v2.1"
+        ));
+    }
+
+    let mut variables = Vec::new();
+    let mut secrets = Vec::new();
+    for variable in array(&collection["variable"]) {
+        if variable["disabled"].as_bool() != Some(true) {
+            add_variable(variable, &mut variables, &mut secrets);
+        }
+    }
+    if let Some(env) = env {
+        let env = read_json(env)?;
+        for variable in array(&env["values"]) {
+            if variable["enabled"].as_bool() != Some(false) {
+                add_variable(variable, &mut variables, &mut secrets);
+            }
+        }
+    }
+
+    let mut definitions = Vec::new();
+    let auth = active_auth(&collection["auth"]);
+    read_items(
+        array(&collection["item"]),
+        &[],
+        auth.as_ref(),
+        &mut definitions,
+    );
+
+    Ok((preamble(&variables, &secrets, existing), definitions))
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let text =
+        std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+
+    serde_json::from_str(&text).context(format!("Failed to parse {}", path.display()))
+}
+
+fn array(value: &Value) -> &[Value] {
+    value.as_array().map_or(&[], |values| values.as_slice())
+}
+
+/// Adds or replaces a variable. Values of secret variables are left out of the script.
+fn add_variable(
+    variable: &Value,
+    variables: &mut Vec<(String, String)>,
+    secrets: &mut Vec<String>,
+) {
+    let key = text(&variable["key"]);
+    if key.is_empty() {
+        return;
+    }
+
+    variables.retain(|(k, _)| *k != key);
+    secrets.retain(|k| *k != key);
+    match variable["type"].as_str() == Some("secret") {
+        true => secrets.push(key),
+        false => variables.push((key, text(&variable["value"]))),
+    }
+}
+
+fn preamble(variables: &[(String, String)], secrets: &[String], existing: &str) -> Option<String> {
+    let mut code = String::new();
+    if !secrets.is_empty() {
+        code.push_str(&format!(
+            "# Secret variables, set them in the environment or .env: {}\n",
+            secrets.join(", ")
+        ));
+    }
+
+    let items: String = variables
+        .iter()
+        .map(|(key, value)| format!("    {}: {},\n", python_str(key), python_str(value)))
+        .collect();
+
+    if existing.contains("def postman_var(") {
+        if !variables.is_empty() {
+            code.push_str(&format!("POSTMAN_VARIABLES.update({{\n{}}})\n", items));
+        }
+        return (!code.is_empty()).then_some(code);
+    }
+
+    code.insert_str(
+        0,
+        "import base64\nimport random\nimport time\nimport uuid\n\n",
+    );
+    code.push_str("# Variables of the Postman collection, environment variables take precedence\n");
+    match variables.is_empty() {
+        true => code.push_str("POSTMAN_VARIABLES = {}\n"),
+        false => code.push_str(&format!("POSTMAN_VARIABLES = {{\n{}}}\n", items)),
+    }
+    code.push_str("\n\n");
+    code.push_str(POSTMAN_VAR);
+
+    Some(code)
+}
+
+/// Parses text with `{{variable}}` references into a template looking them up when run.
+fn template(text: &str) -> Template {
+    let mut template = Template::default();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = rest[start + 2..start + 2 + length].trim();
+
+        template.push_text(&rest[..start]);
+        // Names that could not be put in the generated code are left as they are
+        match name.contains(['\'', '"', '\\', '{', '}', '\n']) {
+            true => template.push_text(&rest[start..start + length + 4]),
+            false => template.push_code(format!("postman_var('{}')", name)),
+        }
+        rest = &rest[start + length + 4..];
+    }
+    template.push_text(rest);
+
+    template
+}
+
+/// The auth block of an item, `None` if it inherits the auth of its parent.
+fn active_auth(auth: &Value) -> Option<Value> {
+    match auth["type"].as_str() {
+        None | Some("inherit") => None,
+        Some(_) => Some(auth.clone()),
+    }
+}
+
+/// Reads requests of the items, recursing into folders which become tags.
+fn read_items(
+    items: &[Value],
+    folders: &[String],
+    auth: Option<&Value>,
+    definitions: &mut Vec<GeneratedDefinition>,
+) {
+    for item in items {
+        let name = text(&item["name"]);
+
+        if item["item"].is_array() {
+            let mut folders = folders.to_vec();
+            folders.push(identifier(&name));
+            let auth = active_auth(&item["auth"]).or_else(|| auth.cloned());
+            read_items(array(&item["item"]), &folders, auth.as_ref(), definitions);
+            continue;
+        }
+
+        if item["request"].is_null() {
+            continue;
+        }
+        match read_request(item, auth) {
+            Some(mut definition) => {
+                definition.tags = folders.to_vec();
+                definitions.push(definition);
+            }
+            None => tracing::warn!("Skipped `{}`, its method is not supported", name),
+        }
+    }
+}
+
+/// Reads a request item, `None` if its method is not supported.
+fn read_request(item: &Value, inherited_auth: Option<&Value>) -> Option<GeneratedDefinition> {
+    let request = &item["request"];
+    let mut notes = Vec::new();
+
+    // A request can also be just its URL
+    let (method, url) = match request {
+        Value::String(url) => ("GET".to_string(), Value::String(url.clone())),
+        request => (
+            request["method"].as_str().unwrap_or("GET").to_uppercase(),
+            request["url"].clone(),
+        ),
+    };
+    if !is_supported(&method) {
+        return None;
+    }
+
+    let url = read_url(&url);
+    let mut params = Vec::new();
+
+    let mut headers: Vec<(String, Template)> = array(&request["header"])
+        .iter()
+        .filter(|header| header["disabled"].as_bool() != Some(true))
+        .map(|header| (text(&header["key"]), template(&text(&header["value"]))))
+        .filter(|(key, _)| !key.is_empty())
+        .collect();
+
+    let body = read_body(&request["body"], &mut headers, &mut notes);
+
+    let auth = active_auth(&request["auth"]).or_else(|| inherited_auth.cloned());
+    if let Some(auth) = auth {
+        add_auth(&auth, &mut headers, &mut params, &mut notes);
+    }
+
+    for event in array(&item["event"]) {
+        let exec = &event["script"]["exec"];
+        let has_code = match exec {
+            Value::String(code) => !code.trim().is_empty(),
+            exec => array(exec).iter().any(|line| !text(line).trim().is_empty()),
+        };
+        if has_code {
+            notes.push(format!(
+                "The Postman {} script was not imported",
+                event["listen"].as_str().unwrap_or("event")
+            ));
+        }
+    }
+
+    let doc = match &request["description"] {
+        Value::String(doc) => Some(doc.clone()),
+        Value::Object(doc) => doc.get("content").map(text),
+        _ => None,
+    }
+    .filter(|doc| !doc.trim().is_empty());
+
+    Some(GeneratedDefinition {
+        name: identifier(&text(&item["name"])),
+        doc,
+        method,
+        url,
+        headers,
+        params,
+        body,
+        notes,
+        ..Default::default()
+    })
+}
+
+/// Reads the URL with its enabled query parameters.
+///
+/// Postman keeps the query percent-encoded as it was typed, so it stays in the URL as it is
+/// instead of becoming `params`, which would be encoded again.
+fn read_url(url: &Value) -> Template {
+    let raw = match url {
+        Value::String(raw) => raw.clone(),
+        url => match url["raw"].as_str() {
+            Some(raw) => raw.to_string(),
+            None => {
+                let host = array(&url["host"]).iter().map(text).collect::<Vec<_>>();
+                let path = array(&url["path"]).iter().map(text).collect::<Vec<_>>();
+                let mut raw = host.join(".");
+                if let Some(protocol) = url["protocol"].as_str() {
+                    raw = format!("{}://{}", protocol, raw);
+                }
+                if !path.is_empty() {
+                    raw = format!("{}/{}", raw, path.join("/"));
+                }
+                raw
+            }
+        },
+    };
+
+    let raw = raw.split_once('#').map_or(raw.as_str(), |(raw, _)| raw);
+    let (path, query) = raw.split_once('?').unwrap_or((raw, ""));
+
+    // Path variables, `/users/:id`
+    let mut path = path.to_string();
+    for variable in array(&url["variable"]) {
+        let key = text(&variable["key"]);
+        if key.is_empty() {
+            continue;
+        }
+        let value = text(&variable["value"]);
+        for suffix in ["/", "?", ""] {
+            let pattern = format!("/:{}{}", key, suffix);
+            if suffix.is_empty() {
+                if path.ends_with(&pattern) {
+                    path = format!("{}/{}", &path[..path.len() - pattern.len()], value);
+                }
+            } else {
+                path = path.replace(&pattern, &format!("/{}{}", value, suffix));
+            }
+        }
+    }
+    if !path.contains("://") && !path.starts_with("{{") {
+        path = format!("http://{}", path);
+    }
+
+    // `raw` only holds the enabled parameters, without it they are read from `query`
+    let query = match (url["raw"].as_str(), url["query"].as_array()) {
+        (None, Some(params)) => params
+            .iter()
+            .filter(|param| param["disabled"].as_bool() != Some(true))
+            .map(|param| match &param["value"] {
+                Value::Null => text(&param["key"]),
+                value => format!("{}={}", text(&param["key"]), text(value)),
+            })
+            .collect::<Vec<_>>()
+            .join("&"),
+        _ => query.to_string(),
+    };
+    if !query.is_empty() {
+        path.push('?');
+        path.push_str(&query);
+    }
+
+    template(&path)
+}
+
+/// Reads the body of a request, setting the content type unless a header sets it already.
+fn read_body(
+    body: &Value,
+    headers: &mut Vec<(String, Template)>,
+    notes: &mut Vec<String>,
+) -> Option<Template> {
+    if body["disabled"].as_bool() == Some(true) {
+        return None;
+    }
+
+    let (body, content_type) = match body["mode"].as_str()? {
+        "raw" => {
+            let raw = text(&body["raw"]);
+            if raw.is_empty() {
+                return None;
+            }
+            let content_type = match body["options"]["raw"]["language"].as_str() {
+                Some("json") => Some("application/json"),
+                Some("xml") => Some("application/xml"),
+                Some("html") => Some("text/html"),
+                Some("text") => Some("text/plain"),
+                _ => None,
+            };
+            (template(&raw), content_type)
+        }
+        "urlencoded" => {
+            let mut form = Template::default();
+            let encode = |text: &str| -> String {
+                url::form_urlencoded::byte_serialize(text.as_bytes()).collect()
+            };
+            let fields = array(&body["urlencoded"])
+                .iter()
+                .filter(|field| field["disabled"].as_bool() != Some(true));
+            for (i, field) in fields.enumerate() {
+                if i > 0 {
+                    form.push_text("&");
+                }
+                form.push_text(&encode(&text(&field["key"])));
+                form.push_text("=");
+                // Only the text around variables is encoded, their values are used as they are
+                let value = text(&field["value"]);
+                let mut rest = value.as_str();
+                while let Some(start) = rest.find("{{") {
+                    let Some(length) = rest[start..].find("}}") else {
+                        break;
+                    };
+                    form.push_text(&encode(&rest[..start]));
+                    form.extend(template(&rest[start..start + length + 2]));
+                    rest = &rest[start + length + 2..];
+                }
+                form.push_text(&encode(rest));
+            }
+            (form, Some("application/x-www-form-urlencoded"))
+        }
+        "graphql" => {
+            let graphql = &body["graphql"];
+            let variables = match &graphql["variables"] {
+                Value::String(variables) if !variables.trim().is_empty() => {
+                    serde_json::from_str(variables).unwrap_or(Value::Null)
+                }
+                _ => Value::Null,
+            };
+            let json = serde_json::json!({
+                "query": text(&graphql["query"]),
+                "variables": variables,
+            });
+            (template(&json.to_string()), Some("application/json"))
+        }
+        mode => {
+            notes.push(format!(
+                "The Postman {} body was not imported, add it by hand",
+                mode
+            ));
+            return None;
+        }
+    };
+
+    if let Some(content_type) = content_type {
+        set_default_header(headers, "Content-Type", content_type);
+    }
+
+    Some(body)
+}
+
+/// Adds the headers, or query parameters, of an auth block.
+fn add_auth(
+    auth: &Value,
+    headers: &mut Vec<(String, Template)>,
+    params: &mut Vec<(String, Template)>,
+    notes: &mut Vec<String>,
+) {
+    let kind = auth["type"].as_str().unwrap_or_default();
+    let value = |key: &str| auth_value(auth, kind, key);
+
+    match kind {
+        "noauth" => {}
+        "bearer" => {
+            let mut header = Template::from("Bearer ");
+            header.extend(template(&value("token")));
+            set_default_header(headers, "Authorization", header);
+        }
+        "basic" => {
+            let credentials = template(&format!("{}:{}", value("username"), value("password")));
+            let mut header = Template::from("Basic ");
+            match credentials.is_text() {
+                true => header.push_text(
+                    &base64::engine::general_purpose::STANDARD.encode(credentials.text()),
+                ),
+                false => header.push_code(format!(
+                    "base64.b64encode(({}).encode()).decode()",
+                    credentials.render_concat()
+                )),
+            }
+            set_default_header(headers, "Authorization", header);
+        }
+        "apikey" => {
+            let key = value("key");
+            let key = match key.is_empty() {
+                true => "X-API-Key".to_string(),
+                false => key,
+            };
+            match value("in").as_str() {
+                "query" => params.push((key, template(&value("value")))),
+                _ => set_default_header(headers, &key, template(&value("value"))),
+            }
+        }
+        kind => notes.push(format!(
+            "Postman {} auth was not imported, add it by hand",
+            kind
+        )),
+    }
+}
+
+/// Reads a field of an auth block, a list of `key`/`value` objects in v2.1 and an object in
+/// v2.0.
+fn auth_value(auth: &Value, kind: &str, key: &str) -> String {
+    match &auth[kind] {
+        Value::Array(fields) => fields
+            .iter()
+            .find(|field| field["key"].as_str() == Some(key))
+            .map(|field| text(&field["value"]))
+            .unwrap_or_default(),
+        fields => text(&fields[key]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn definitions(collection: Value) -> Vec<GeneratedDefinition> {
+        let mut definitions = Vec::new();
+        let auth = active_auth(&collection["auth"]);
+        read_items(
+            array(&collection["item"]),
+            &[],
+            auth.as_ref(),
+            &mut definitions,
+        );
+        definitions
+    }
+
+    fn request(name: &str, request: Value) -> Value {
+        json!({ "name": name, "request": request })
+    }
+
+    fn headers(definition: &GeneratedDefinition) -> Vec<(&str, String)> {
+        definition
+            .headers
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.render()))
+            .collect()
+    }
+
+    #[test]
+    fn variables() {
+        let definitions = definitions(json!({
+            "item": [request("get user", json!({
+                "method": "GET",
+                "url": "{{base_url}}/users?page={{page}}",
+                "header": [{ "key": "X-Trace", "value": "{{$guid}}" }],
+            }))],
+        }));
+        assert_eq!(
+            definitions[0].url.render(),
+            "f\"{postman_var('base_url')}/users?page={postman_var('page')}\""
+        );
+        assert_eq!(
+            headers(&definitions[0]),
+            [("X-Trace", "postman_var('$guid')".to_string())]
+        );
+
+        let mut variables = Vec::new();
+        let mut secrets = Vec::new();
+        for variable in [
+            json!({ "key": "base_url", "value": "http://localhost" }),
+            json!({ "key": "token", "value": "hunter2", "type": "secret" }),
+            json!({ "key": "page", "value": 1 }),
+        ] {
+            add_variable(&variable, &mut variables, &mut secrets);
+        }
+        let preamble = preamble(&variables, &secrets, "").unwrap();
+        assert!(
+            preamble.contains("# Secret variables, set them in the environment or .env: token")
+        );
+        assert!(preamble.contains(
+            "POSTMAN_VARIABLES = {\n    \"base_url\": \"http://localhost\",\n    \"page\": \"1\",\n}"
+        ));
+        assert!(!preamble.contains("hunter2"));
+        assert!(preamble.contains("def postman_var(name):"));
+
+        // A second import only adds its variables
+        let preamble = super::preamble(&variables, &[], POSTMAN_VAR).unwrap();
+        assert!(preamble.starts_with("POSTMAN_VARIABLES.update({"));
+    }
+
+    #[test]
+    fn auth() {
+        let bearer =
+            json!({ "type": "bearer", "bearer": [{ "key": "token", "value": "{{token}}" }] });
+        let basic = json!({ "type": "basic", "basic": [
+            { "key": "username", "value": "neo" },
+            { "key": "password", "value": "matrix" },
+        ] });
+        let apikey = json!({ "type": "apikey", "apikey": [
+            { "key": "key", "value": "api_key" },
+            { "key": "value", "value": "secret" },
+            { "key": "in", "value": "query" },
+        ] });
+
+        let definitions = definitions(json!({
+            "auth": bearer,
+            "item": [
+                request("inherited", json!({ "method": "GET", "url": "example.com" })),
+                request("basic", json!({ "method": "GET", "url": "example.com", "auth": basic })),
+                request("none", json!({ "method": "GET", "url": "example.com", "auth": { "type": "noauth" } })),
+                {
+                    "name": "folder",
+                    "auth": apikey,
+                    "item": [
+                        request("apikey", json!({ "method": "GET", "url": "example.com" })),
+                        request("folder inherited", json!({
+                            "method": "GET",
+                            "url": "example.com",
+                            "auth": { "type": "inherit" },
+                        })),
+                    ],
+                },
+            ],
+        }));
+
+        assert_eq!(
+            headers(&definitions[0]),
+            [(
+                "Authorization",
+                "f\"Bearer {postman_var('token')}\"".to_string()
+            )]
+        );
+        assert_eq!(
+            headers(&definitions[1]),
+            [("Authorization", "\"Basic bmVvOm1hdHJpeA==\"".to_string())]
+        );
+        assert!(definitions[2].headers.is_empty());
+        for definition in &definitions[3..] {
+            assert_eq!(definition.tags, ["folder"]);
+            assert!(definition.headers.is_empty());
+            assert_eq!(
+                definition.params,
+                [("api_key".to_string(), "secret".into())]
+            );
+        }
+    }
+
+    #[test]
+    fn basic_auth_with_variables() {
+        let definitions = definitions(json!({
+            "item": [request("basic", json!({
+                "method": "GET",
+                "url": "example.com",
+                "auth": { "type": "basic", "basic": { "username": "{{user}}", "password": "x" } },
+            }))],
+        }));
+        assert_eq!(
+            headers(&definitions[0]),
+            [(
+                "Authorization",
+                "f\"Basic {base64.b64encode((postman_var('user') + ':x').encode()).decode()}\""
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn urlencoded_body() {
+        let definitions = definitions(json!({
+            "item": [request("login", json!({
+                "method": "POST",
+                "url": "example.com/login",
+                "body": {
+                    "mode": "urlencoded",
+                    "urlencoded": [
+                        { "key": "user name", "value": "neo&trinity" },
+                        { "key": "password", "value": "a b{{password}}c" },
+                        { "key": "remember", "value": "1", "disabled": true },
+                    ],
+                },
+            }))],
+        }));
+
+        assert_eq!(
+            definitions[0].body.as_ref().unwrap().render(),
+            "f\"user+name=neo%26trinity&password=a+b{postman_var('password')}c\""
+        );
+        assert_eq!(
+            headers(&definitions[0]),
+            [(
+                "Content-Type",
+                "\"application/x-www-form-urlencoded\"".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn path_variables() {
+        let definitions = definitions(json!({
+            "item": [request("get user", json!({
+                "method": "GET",
+                "url": {
+                    "raw": "https://example.com/users/:id/posts/:post",
+                    "variable": [
+                        { "key": "id", "value": "7" },
+                        { "key": "post", "value": "{{post}}" },
+                    ],
+                },
+            }))],
+        }));
+
+        assert_eq!(
+            definitions[0].url.render(),
+            "f\"https://example.com/users/7/posts/{postman_var('post')}\""
+        );
+    }
+
+    #[test]
+    fn query_kept_encoded() {
+        let definitions = definitions(json!({
+            "item": [
+                request("search", json!({
+                    "method": "GET",
+                    "url": {
+                        "raw": "https://example.com/search?q=a%20b&sort=name#top",
+                        "query": [
+                            { "key": "q", "value": "a%20b" },
+                            { "key": "sort", "value": "name" },
+                            { "key": "page", "value": "2", "disabled": true },
+                        ],
+                    },
+                })),
+                request("structured", json!({
+                    "method": "GET",
+                    "url": {
+                        "protocol": "https",
+                        "host": ["example", "com"],
+                        "path": ["search"],
+                        "query": [
+                            { "key": "q", "value": "1%2B1" },
+                            { "key": "flag", "value": null },
+                            { "key": "page", "value": "2", "disabled": true },
+                        ],
+                    },
+                })),
+            ],
+        }));
+
+        assert_eq!(
+            definitions[0].url.render(),
+            "\"https://example.com/search?q=a%20b&sort=name\""
+        );
+        assert_eq!(
+            definitions[1].url.render(),
+            "\"https://example.com/search?q=1%2B1&flag\""
+        );
+        assert!(definitions.iter().all(|d| d.params.is_empty()));
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let definitions = definitions(json!({
+            "item": [
+                request("Get user", json!({ "method": "GET", "url": "example.com/a" })),
+                request("get_user", json!({ "method": "GET", "url": "example.com/b" })),
+                request("trace", json!({ "method": "TRACE", "url": "example.com" })),
+            ],
+        }));
+        assert_eq!(definitions.len(), 2);
+
+        let path = std::env::temp_dir().join(format!("neocurl-postman-{}.py", std::process::id()));
+        let existing = "import neocurl as nc\n\n@nc.define\ndef get_user(client):\n    pass\n";
+        let names = super::super::append(&path, existing, None, definitions);
+        let script = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(names.unwrap(), ["get_user_2", "get_user_3"]);
+        let script = script.unwrap();
+        assert!(script.starts_with(existing));
+        assert!(script.contains("def get_user_2(client):"));
+        assert!(script.contains("def get_user_3(client):"));
+    }
+}
@@ -1,9 +1,24 @@
 pub mod api;
+mod importer;
+mod openapi;
+mod repl;
+pub mod report;
+pub mod select;
+pub mod testing;
+mod venv;
 pub mod vm;
+mod watch;
+
+pub use api::LogOutput;
+pub use report::{DefinitionError, DefinitionResult, RunReport};
+pub use vm::{Vm, VmBuilder};
 
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use owo_colors::{OwoColorize, XtermColors};
+use report::ReportArgs;
+use select::SelectArgs;
+use std::process::ExitCode;
 
 const DEFAULT_FILE: &str = include_str!("default.py");
 
@@ -11,6 +26,7 @@ const DEFAULT_FILE: &str = include_str!("default.py");
 #[derive(Clone, Parser)]
 #[clap(version)]
 struct Args {
+    /// Script to load, or a directory or glob of scripts
     #[clap(long, short, default_value = "ncurl.py")]
     file: String,
 
@@ -23,13 +39,68 @@ struct Args {
 enum Commands {
     Init,
     Repl,
-    Run { name: String },
-    List,
-    Test,
+    #[command(group(clap::ArgGroup::new("shows_requests").args(["print_curl", "dry_run"]).multiple(true)))]
+    Run {
+        #[clap(flatten)]
+        select: SelectArgs,
+
+        #[clap(flatten)]
+        report: ReportArgs,
+
+        /// Print every request as a curl command before sending it
+        #[clap(long)]
+        print_curl: bool,
+
+        /// Answer requests with the stubs of the script instead of sending them
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Replace secrets like tokens and passwords in printed curl commands
+        #[clap(long, requires = "shows_requests")]
+        redact: bool,
+
+        /// Write every request and response to a HAR file
+        #[clap(long)]
+        har: Option<std::path::PathBuf>,
+    },
+    List {
+        /// Show tags, descriptions, docstrings and source locations
+        #[clap(long, short)]
+        verbose: bool,
+    },
+    /// Generate definitions from other formats and append them to the script
+    Import {
+        #[clap(subcommand)]
+        source: importer::ImportSource,
+    },
+    /// Re-run definitions whenever the script changes
+    Watch {
+        #[clap(flatten)]
+        select: SelectArgs,
+
+        /// Watch all definitions, running them like `test`
+        #[clap(long)]
+        all: bool,
+    },
+    Test {
+        #[clap(flatten)]
+        report: ReportArgs,
+
+        /// Write a JUnit XML report to this path
+        #[clap(long)]
+        junit: Option<std::path::PathBuf>,
+
+        /// Write every request and response to a HAR file
+        #[clap(long)]
+        har: Option<std::path::PathBuf>,
+    },
 }
 
-/// Main function to run the CLI
-pub fn run() -> Result<()> {
+/// Runs the CLI with the arguments of the process.
+///
+/// Returns a failure exit code if any definition failed. To run definitions without the CLI,
+/// use [`Vm`] directly.
+pub fn run() -> Result<ExitCode> {
     let span = tracing::info_span!("run");
     let _enter = span.enter();
 
@@ -41,7 +112,7 @@ pub fn run() -> Result<()> {
                 "File {} already exists, skipping initialization",
                 &args.file
             );
-            return Ok(());
+            return Ok(ExitCode::SUCCESS);
         }
 
         let default_file = DEFAULT_FILE
@@ -52,67 +123,209 @@ pub fn run() -> Result<()> {
             .context(format!("Failed to write default file to {}", &args.file))?;
         println!("Initialized successfully at {}.", &args.file);
 
-        return Ok(());
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Commands::Import { source } = &args.command {
+        let names = importer::import(std::path::Path::new(&args.file), source)?;
+        println!("Added {} to {}.", names.join(", "), &args.file);
+
+        return Ok(ExitCode::SUCCESS);
     }
 
-    let vm = vm::Vm::builder()
-        .load(args.file)
+    // Every run of watch mode loads the script into a new VM
+    if let Commands::Watch { select, all } = &args.command {
+        watch::watch(&args.file, select, *all)?;
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Logs must not get mixed with a report printed to stdout
+    let output = match &args.command {
+        Commands::Run { report, .. } | Commands::Test { report, .. } if report.to_stdout() => {
+            LogOutput::Stderr
+        }
+        _ => LogOutput::Stdout,
+    };
+
+    let (print_curl, dry_run) = match &args.command {
+        Commands::Run {
+            print_curl,
+            dry_run,
+            redact,
+            ..
+        } => {
+            let print_curl = match (print_curl, redact) {
+                (_, true) => api::PrintCurl::Redacted,
+                (true, false) => api::PrintCurl::On,
+                (false, false) => api::PrintCurl::Off,
+            };
+            (print_curl, *dry_run)
+        }
+        _ => (api::PrintCurl::Off, false),
+    };
+
+    let mut vm = vm::Vm::builder()
+        .load(&args.file)
         .context("Failed to load source to VM")?
+        .output(output)
+        .print_curl(print_curl)
+        .dry_run(dry_run)
         .build()
         .context("Failed to build VM")?;
 
-    vm.init().context("Failed to run VM")?;
+    let result = vm
+        .init()
+        .context("Failed to run VM")
+        .and_then(|_| run_command(&mut vm, &args));
+    // Cleanup runs however the command ended, also if the script failed to load
+    let cleanup = vm.cleanup().context("Failed to cleanup VM");
 
-    match args.command {
-        Commands::List => {
+    let mut run_report = result?;
+    // Written after cleanup, to include the requests of on_cleanup functions
+    let har = match &args.command {
+        Commands::Run { har, .. } | Commands::Test { har, .. } => har.as_ref(),
+        _ => None,
+    };
+    if let (Some(run_report), Some(path)) = (run_report.as_mut(), har) {
+        run_report.requests.extend(vm.take_requests());
+        report::write_har(run_report, path)?;
+    }
+    cleanup?;
+
+    Ok(match run_report.is_none_or(|r| r.failed == 0) {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
+
+/// Runs a command on an initialized VM, returning the report of the definitions it ran
+fn run_command(vm: &mut Vm, args: &Args) -> Result<Option<RunReport>> {
+    match &args.command {
+        Commands::List { verbose } => {
             println!("Available definitions:");
-            for (i, def) in vm.list_definitions().iter().enumerate() {
-                println!("{}: {}", i, def);
+            for (i, def) in vm.definitions().iter().enumerate() {
+                if *verbose {
+                    print_definition(i, def);
+                } else {
+                    println!("{}: {}", i, def.name);
+                }
             }
+
+            Ok(None)
         }
-        Commands::Run { name } => {
-            vm.run_definition(name, false)?;
-
-            let (calls_passed, calls_failed) = *api::CALLS.lock().unwrap();
-            println!(
-                "{} {}{}{}",
-                "Call results:".color(XtermColors::DarkGray),
-                calls_passed.green(),
-                "/".color(XtermColors::DarkGray),
-                calls_failed.red()
-            );
+        Commands::Run { select, report, .. } => {
+            if select.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "No definitions selected, pass a name, pattern or --tag"
+                ));
+            }
+
+            let names = vm.select_definitions(&select.selection()?);
+            if names.is_empty() {
+                return Err(anyhow::anyhow!("No definitions match the selection"));
+            }
 
-            vm.cleanup().context("Failed to cleanup VM")?;
+            let mut writer = report.writer()?;
+            let run_report = vm.run_definitions(&names, false, |result| match writer.as_mut() {
+                Some(writer) => writer.definition(result),
+                None => Ok(()),
+            })?;
 
-            if calls_failed > 0 {
-                std::process::exit(1);
+            if let Some(writer) = writer {
+                writer.finish(&run_report)?;
             }
+
+            let (calls_passed, calls_failed) = *vm.state().calls.lock().unwrap();
+            if !report.to_stdout() {
+                println!(
+                    "{} {}{}{}",
+                    "Call results:".color(XtermColors::DarkGray),
+                    calls_passed.green(),
+                    "/".color(XtermColors::DarkGray),
+                    calls_failed.red()
+                );
+            }
+
+            Ok(Some(run_report))
         }
         Commands::Repl => {
-            unimplemented!("REPL mode is not implemented yet");
-            // repl::repl(&mut runtime)?;
+            repl::repl(vm)?;
+
+            Ok(None)
         }
-        Commands::Test => {
-            vm.run_tests()?;
-
-            let (tests_passed, tests_failed) = *api::TESTS.lock().unwrap();
-            println!(
-                "{} {}{}{}",
-                "Test results:".color(XtermColors::DarkGray),
-                tests_passed.green(),
-                "/".color(XtermColors::DarkGray),
-                tests_failed.red()
-            );
+        Commands::Test { report, junit, .. } => {
+            let mut writer = report.writer()?;
+            let run_report = vm.run_tests_with(|result| match writer.as_mut() {
+                Some(writer) => writer.definition(result),
+                None => Ok(()),
+            })?;
+
+            if let Some(writer) = writer {
+                writer.finish(&run_report)?;
+            }
+
+            if let Some(path) = junit {
+                let suite = std::path::Path::new(&args.file)
+                    .file_stem()
+                    .map_or(args.file.clone(), |s| s.to_string_lossy().to_string());
+                report::write_junit(&run_report, &suite, path)?;
+            }
 
-            if tests_failed > 0 {
-                std::process::exit(1);
+            let (tests_passed, tests_failed) = *vm.state().tests.lock().unwrap();
+            if !report.to_stdout() {
+                println!(
+                    "{} {}{}{}",
+                    "Test results:".color(XtermColors::DarkGray),
+                    tests_passed.green(),
+                    "/".color(XtermColors::DarkGray),
+                    tests_failed.red()
+                );
             }
+
+            Ok(Some(run_report))
         }
         _ => {
             tracing::error!("Unknown command: {:?}", args.command);
-            return Err(anyhow::anyhow!("Unknown command"));
+            Err(anyhow::anyhow!("Unknown command"))
         }
     }
+}
+
+/// Prints a definition with all of its metadata
+fn print_definition(i: usize, def: &api::DefinitionMeta) {
+    if def.tags.is_empty() {
+        println!("{}: {}", i, def.name);
+    } else {
+        println!(
+            "{}: {} {}",
+            i,
+            def.name,
+            format!("[{}]", def.tags.join(", ")).color(XtermColors::DarkGray)
+        );
+    }
 
-    Ok(())
+    if let Some(description) = &def.description {
+        println!("    {}", description);
+    }
+    if let Some(doc) = &def.doc {
+        for line in doc.lines() {
+            println!("    {}", line.color(XtermColors::DarkGray));
+        }
+    }
+    if !def.depends_on.is_empty() {
+        println!("    depends on: {}", def.depends_on.join(", "));
+    }
+    if let Some(timeout) = def.timeout {
+        println!("    timeout: {}ms", timeout);
+    }
+    if let Some(reason) = &def.skip {
+        match reason.is_empty() {
+            true => println!("    {}", "skipped".yellow()),
+            false => println!("    {} {}", "skipped:".yellow(), reason),
+        }
+    }
+    if let (Some(file), Some(line)) = (&def.file, def.line) {
+        println!("    {}:{}", file, line);
+    }
 }
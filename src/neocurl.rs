@@ -1,8 +1,15 @@
-fn main() {
-    tracing_subscriber::fmt::init();
+use std::process::ExitCode;
 
-    if let Err(e) = neocurl::run() {
-        tracing::error!("Error occured: {:?}", e);
-        std::process::exit(1);
+fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    match neocurl::run() {
+        Ok(code) => code,
+        Err(e) => {
+            tracing::error!("Error occured: {:?}", e);
+            ExitCode::FAILURE
+        }
     }
 }
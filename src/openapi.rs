@@ -0,0 +1,344 @@
+//! Reading OpenAPI 3 documents.
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::Path;
+
+/// Operation keys of a path item, with the method names neocurl sends.
+const METHODS: [(&str, &str); 8] = [
+    ("get", "GET"),
+    ("put", "PUT"),
+    ("post", "POST"),
+    ("delete", "DELETE"),
+    ("options", "OPTIONS"),
+    ("head", "HEAD"),
+    ("patch", "PATCH"),
+    ("trace", "TRACE"),
+];
+
+/// Follows at most this many `$ref`s in a row, to stop at cycles.
+const MAX_REFS: usize = 32;
+
+/// A loaded OpenAPI 3 document, in YAML or JSON.
+#[derive(Debug)]
+pub struct Spec {
+    document: Value,
+}
+
+/// An operation of a path, with the parameters declared on the path item too.
+#[derive(Debug)]
+pub struct Operation<'a> {
+    /// Method in upper case, `GET`, `POST`, ...
+    pub method: &'static str,
+    /// The templated path, `/users/{id}`
+    pub path: &'a str,
+    pub value: &'a Value,
+    /// Resolved parameters, those of the operation replacing path item ones of the same name
+    pub parameters: Vec<&'a Value>,
+}
+
+impl Spec {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+
+        Self::parse(&text).context(format!("Failed to load {}", path.display()))
+    }
+
+    /// Parses a document in YAML or JSON.
+    pub fn parse(text: &str) -> Result<Self> {
+        // JSON is YAML too
+        let yaml: serde_yaml::Value = serde_yaml::from_str(text).context("Failed to parse")?;
+        let document = json(yaml);
+
+        let version = document["openapi"].as_str().unwrap_or_default();
+        if !version.starts_with("3.") {
+            return Err(anyhow::anyhow!(
+                "Not an OpenAPI 3 document, only OpenAPI 3.0 and 3.1 are supported"
+            ));
+        }
+
+        Ok(Spec { document })
+    }
+
+    pub fn document(&self) -> &Value {
+        &self.document
+    }
+
+    /// Follows `$ref`s within the document. References to other files are not followed.
+    pub fn resolve<'a>(&'a self, mut value: &'a Value) -> &'a Value {
+        for _ in 0..MAX_REFS {
+            let Some(pointer) = value["$ref"].as_str().and_then(|r| r.strip_prefix('#')) else {
+                break;
+            };
+            match self.document.pointer(pointer) {
+                Some(target) => value = target,
+                None => {
+                    tracing::warn!("Unresolved reference: #{}", pointer);
+                    break;
+                }
+            }
+        }
+
+        value
+    }
+
+    /// All operations, in the order of the document.
+    pub fn operations(&self) -> Vec<Operation<'_>> {
+        let mut operations = Vec::new();
+        let Some(paths) = self.document["paths"].as_object() else {
+            return operations;
+        };
+
+        for path in paths.keys() {
+            operations.extend(METHODS.iter().filter_map(|(_, m)| self.operation(m, path)));
+        }
+
+        operations
+    }
+
+    /// The operation for `method`, in upper case, on the templated `path`.
+    pub fn operation<'a>(&'a self, method: &str, path: &'a str) -> Option<Operation<'a>> {
+        let item = self.resolve(self.document["paths"].get(path)?);
+        let (key, method) = METHODS.iter().find(|(_, m)| *m == method)?;
+        let value = item.get(key)?;
+
+        let mut parameters: Vec<&Value> = Vec::new();
+        let declared = item["parameters"].as_array().into_iter().flatten();
+        for parameter in declared.chain(value["parameters"].as_array().into_iter().flatten()) {
+            let parameter = self.resolve(parameter);
+            parameters.retain(|p| p["name"] != parameter["name"] || p["in"] != parameter["in"]);
+            parameters.push(parameter);
+        }
+
+        Some(Operation {
+            method,
+            path,
+            value,
+            parameters,
+        })
+    }
+
+    /// Finds the templated path documenting a request path, which may start with the path of a
+    /// server. Paths with more literal segments win, `/users/me` over `/users/{id}`.
+    pub fn match_path(&self, path: &str) -> Option<&str> {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        let mut prefixes: Vec<Vec<String>> = vec![Vec::new()];
+        for server in self.document["servers"].as_array().into_iter().flatten() {
+            let mut url = server["url"].as_str().unwrap_or_default().to_string();
+            for (name, variable) in server["variables"].as_object().into_iter().flatten() {
+                let default = variable["default"].as_str().unwrap_or_default();
+                url = url.replace(&format!("{{{}}}", name), default);
+            }
+            let url_path = match url.split_once("://") {
+                Some((_, rest)) => rest.split_once('/').map_or("", |(_, path)| path),
+                None => url.as_str(),
+            };
+            prefixes.push(
+                url_path
+                    .split('/')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            );
+        }
+
+        let mut best: Option<(&str, usize)> = None;
+        for template in self.document["paths"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(k, _)| k)
+        {
+            let parts: Vec<&str> = template.split('/').filter(|s| !s.is_empty()).collect();
+            for prefix in &prefixes {
+                let Some(rest) = strip_segments(&segments, prefix) else {
+                    continue;
+                };
+                if rest.len() != parts.len() {
+                    continue;
+                }
+
+                let mut literals = 0;
+                let matches = rest.iter().zip(&parts).all(|(segment, part)| {
+                    match part.starts_with('{') && part.ends_with('}') {
+                        true => !segment.is_empty(),
+                        false => {
+                            literals += 1;
+                            segment == part
+                        }
+                    }
+                });
+                if matches && best.is_none_or(|(_, most)| literals > most) {
+                    best = Some((template, literals));
+                }
+            }
+        }
+
+        best.map(|(template, _)| template)
+    }
+
+    /// URL of the first server, with variables set to their defaults.
+    pub fn server_url(&self) -> Option<String> {
+        let server = self.document["servers"].as_array()?.first()?;
+        let mut url = server["url"].as_str()?.to_string();
+        if let Some(variables) = server["variables"].as_object() {
+            for (name, variable) in variables {
+                let default = variable["default"].as_str().unwrap_or_default();
+                url = url.replace(&format!("{{{}}}", name), default);
+            }
+        }
+
+        Some(url.trim_end_matches('/').to_string())
+    }
+}
+
+/// The segments after `prefix`, if `segments` start with it.
+fn strip_segments<'a, 'b>(segments: &'b [&'a str], prefix: &[String]) -> Option<&'b [&'a str]> {
+    if segments.len() < prefix.len() || segments.iter().zip(prefix).any(|(s, p)| s != p) {
+        return None;
+    }
+
+    Some(&segments[prefix.len()..])
+}
+
+/// Converts YAML to JSON, turning keys like the unquoted status codes of responses into
+/// strings.
+fn json(yaml: serde_yaml::Value) -> Value {
+    match yaml {
+        serde_yaml::Value::Null => Value::Null,
+        serde_yaml::Value::Bool(b) => Value::Bool(b),
+        serde_yaml::Value::Number(n) => serde_json::to_value(n).unwrap_or(Value::Null),
+        serde_yaml::Value::String(s) => Value::String(s),
+        serde_yaml::Value::Sequence(values) => Value::Array(values.into_iter().map(json).collect()),
+        serde_yaml::Value::Mapping(mapping) => Value::Object(
+            mapping
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = match json(key) {
+                        Value::String(key) => key,
+                        key => key.to_string(),
+                    };
+                    (key, json(value))
+                })
+                .collect(),
+        ),
+        serde_yaml::Value::Tagged(tagged) => json(tagged.value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r##"
+openapi: 3.0.3
+servers:
+  - url: https://{host}/api/v1
+    variables:
+      host:
+        default: example.com
+paths:
+  /users/{id}:
+    parameters:
+      - { name: id, in: path, required: true, schema: { type: string } }
+      - { name: verbose, in: query, schema: { type: boolean } }
+    get:
+      parameters:
+        - { name: id, in: path, required: true, schema: { type: integer } }
+      responses:
+        200: { description: OK }
+  /users/me:
+    get:
+      responses:
+        200: { description: OK }
+  /users/{id}/posts/{post}:
+    $ref: "#/components/pathItems/posts"
+components:
+  pathItems:
+    posts:
+      delete:
+        responses:
+          204: { description: Deleted }
+"##;
+
+    #[test]
+    fn literal_segments_win() {
+        let spec = Spec::parse(SPEC).unwrap();
+        assert_eq!(spec.match_path("/users/me"), Some("/users/me"));
+        assert_eq!(spec.match_path("/users/7"), Some("/users/{id}"));
+        assert_eq!(
+            spec.match_path("/users/7/posts/1"),
+            Some("/users/{id}/posts/{post}")
+        );
+        assert_eq!(spec.match_path("/users"), None);
+        assert_eq!(spec.match_path("/users/7/posts"), None);
+    }
+
+    #[test]
+    fn server_prefixes() {
+        let spec = Spec::parse(SPEC).unwrap();
+        assert_eq!(spec.match_path("/api/v1/users/me"), Some("/users/me"));
+        assert_eq!(spec.match_path("/api/v1/users/7"), Some("/users/{id}"));
+        assert_eq!(spec.match_path("/api/v2/users/7"), None);
+        assert_eq!(
+            spec.server_url().as_deref(),
+            Some("https://example.com/api/v1")
+        );
+    }
+
+    #[test]
+    fn operations() {
+        let spec = Spec::parse(SPEC).unwrap();
+        let operations: Vec<(&str, &str)> = spec
+            .operations()
+            .iter()
+            .map(|operation| (operation.method, operation.path))
+            .collect();
+        assert_eq!(
+            operations,
+            [
+                ("GET", "/users/{id}"),
+                ("GET", "/users/me"),
+                ("DELETE", "/users/{id}/posts/{post}")
+            ]
+        );
+
+        // Parameters of the operation replace those of the path item
+        let operation = spec.operation("GET", "/users/{id}").unwrap();
+        let parameters: Vec<(&str, &str)> = operation
+            .parameters
+            .iter()
+            .map(|p| {
+                (
+                    p["name"].as_str().unwrap(),
+                    p["schema"]["type"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(parameters, [("verbose", "boolean"), ("id", "integer")]);
+
+        // Unquoted status codes are keys too
+        assert!(operation.value["responses"]["200"].is_object());
+    }
+
+    #[test]
+    fn only_openapi_3() {
+        assert!(Spec::parse("swagger: '2.0'\npaths: {}").is_err());
+        assert!(Spec::parse(r#"{"openapi": "3.1.0", "paths": {}}"#).is_ok());
+    }
+
+    #[test]
+    fn reference_cycles() {
+        let spec = Spec::parse(
+            r##"{"openapi": "3.1.0", "components": {"schemas": {
+                "a": {"$ref": "#/components/schemas/b"},
+                "b": {"$ref": "#/components/schemas/a"}
+            }}}"##,
+        )
+        .unwrap();
+        let value = spec.resolve(&spec.document()["components"]["schemas"]["a"]);
+        assert!(value["$ref"].is_string());
+    }
+}
@@ -1,43 +1,150 @@
 //! Module for the REPL (Read-Eval-Print Loop) interface.
 
-use crate::lua::LuaRuntime;
+use crate::{select::SelectArgs, vm::Vm};
 use anyhow::Result;
-use linefeed::{Interface, ReadResult};
+use linefeed::{
+    Completer, Completion, Interface, Prompter, ReadResult, Terminal, complete::escape,
+};
+use owo_colors::{OwoColorize, XtermColors};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
-/// Starts a Read-Eval-Print Loop (REPL) for the Lua runtime.
-pub fn repl(runtime: &mut LuaRuntime) -> Result<()> {
+const COMMANDS: &[&str] = &[
+    "list", "run", "test", "reload", "py", "help", "exit", "quit",
+];
+
+const HELP: &str = "Commands:
+  list          List available definitions
+  run <name>... Run definitions by name or glob pattern
+  test          Run all definitions as tests
+  reload        Reload the script from disk and run on_init again
+  py <code>     Evaluate a line of Python in the script namespace
+  help          Show this message
+  exit, quit    Leave the REPL";
+
+/// Completes command names and, after `run`, definition names.
+struct ReplCompleter {
+    definitions: Arc<Mutex<Vec<String>>>,
+}
+
+impl<Term: Terminal> Completer<Term> for ReplCompleter {
+    fn complete(
+        &self,
+        word: &str,
+        prompter: &Prompter<Term>,
+        start: usize,
+        _end: usize,
+    ) -> Option<Vec<Completion>> {
+        let line = &prompter.buffer()[..start];
+        let mut words = line.split_whitespace();
+
+        let candidates: Vec<String> = match (words.next(), words.next()) {
+            (None, _) => COMMANDS.iter().map(|c| c.to_string()).collect(),
+            (Some("run"), None) => self.definitions.lock().unwrap().clone(),
+            _ => return None,
+        };
+
+        Some(
+            candidates
+                .into_iter()
+                .filter(|c| c.starts_with(word))
+                .map(|c| Completion::simple(escape(&c).into_owned()))
+                .collect(),
+        )
+    }
+}
+
+/// Returns the path of the history file, if a home directory is known.
+fn history_path() -> Option<PathBuf> {
+    std::env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(".ncurl_history"))
+}
+
+/// Starts a Read-Eval-Print Loop (REPL) on an initialized VM.
+pub fn repl(vm: &mut Vm) -> Result<()> {
     let reader = Interface::new("ncurl")?;
     reader.set_prompt(">> ")?;
 
+    let definitions = Arc::new(Mutex::new(vm.list_definitions()));
+    reader.set_completer(Arc::new(ReplCompleter {
+        definitions: definitions.clone(),
+    }));
+
+    let history = history_path();
+    if let Some(path) = &history
+        && let Err(e) = reader.load_history(path)
+    {
+        tracing::debug!("Failed to load history from {}: {}", path.display(), e);
+    }
+
     loop {
         match reader.read_line()? {
             ReadResult::Input(line) => {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.is_empty() {
+                let line = line.trim();
+                if line.is_empty() {
                     continue;
                 }
+                reader.add_history_unique(line.to_string());
+
+                let (cmd, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                let rest = rest.trim();
 
-                match parts[0] {
+                match cmd {
                     "list" => {
-                        for (i, (_, name)) in runtime.list_refinitions().iter().enumerate() {
-                            println!("{}: {}", i + 1, name);
+                        for (i, name) in vm.list_definitions().iter().enumerate() {
+                            println!("{}: {}", i, name);
                         }
                     }
-                    "run" if parts.len() >= 2 => {
-                        let name = parts[1].to_string();
-
-                        runtime.run_definition(name)?;
-                        let _ = runtime.test_summary();
+                    "run" if !rest.is_empty() => {
+                        *vm.state().calls.lock().unwrap() = (0, 0);
+                        match run(vm, rest) {
+                            Ok(()) => {
+                                let (passed, failed) = *vm.state().calls.lock().unwrap();
+                                print_results("Call results:", passed, failed);
+                            }
+                            Err(e) => eprintln!("{:#}", e),
+                        }
                     }
                     "test" => {
-                        runtime.run_tests()?;
+                        *vm.state().tests.lock().unwrap() = (0, 0);
+                        match vm.run_tests() {
+                            Ok(_) => {
+                                let (passed, failed) = *vm.state().tests.lock().unwrap();
+                                print_results("Test results:", passed, failed);
+                            }
+                            Err(e) => eprintln!("{:#}", e),
+                        }
                     }
+                    "reload" => match vm.reload() {
+                        Ok(()) => {
+                            *definitions.lock().unwrap() = vm.list_definitions();
+                            println!(
+                                "Reloaded {} definitions.",
+                                definitions.lock().unwrap().len()
+                            );
+                        }
+                        Err(e) => eprintln!("{:#}", e),
+                    },
+                    "py" if !rest.is_empty() => match vm.eval(rest) {
+                        Ok(Some(value)) => println!("{}", value),
+                        Ok(None) => {}
+                        Err(e) => eprintln!("{:#}", e),
+                    },
+                    "run" | "py" => eprintln!(
+                        "Usage: {} <{}>",
+                        cmd,
+                        if cmd == "run" { "name" } else { "code" }
+                    ),
+                    "help" => println!("{}", HELP),
                     "exit" | "quit" => {
                         println!("Exiting REPL.");
                         break;
                     }
                     cmd => {
-                        eprintln!("Unknown command: {}", cmd);
+                        eprintln!("Unknown command: {} (type `help` for a list)", cmd);
                     }
                 }
             }
@@ -52,5 +159,38 @@ pub fn repl(runtime: &mut LuaRuntime) -> Result<()> {
         }
     }
 
+    if let Some(path) = &history
+        && let Err(e) = reader.save_history(path)
+    {
+        tracing::warn!("Failed to save history to {}: {}", path.display(), e);
+    }
+
     Ok(())
 }
+
+/// Runs all definitions matching the whitespace separated glob patterns.
+fn run(vm: &Vm, patterns: &str) -> Result<()> {
+    let select = SelectArgs {
+        names: patterns.split_whitespace().map(String::from).collect(),
+        ..Default::default()
+    };
+
+    let names = vm.select_definitions(&select.selection()?);
+    if names.is_empty() {
+        return Err(anyhow::anyhow!("No definitions match: {}", patterns));
+    }
+
+    vm.run_definitions(&names, false, |_| Ok(()))?;
+
+    Ok(())
+}
+
+fn print_results(label: &str, passed: u32, failed: u32) {
+    println!(
+        "{} {}{}{}",
+        label.color(XtermColors::DarkGray),
+        passed.green(),
+        "/".color(XtermColors::DarkGray),
+        failed.red()
+    );
+}
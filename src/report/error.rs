@@ -0,0 +1,119 @@
+use pyo3::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why a definition failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DefinitionError {
+    /// Exception type, e.g. `AssertionError`
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub message: String,
+    /// Frames of the traceback, innermost last
+    pub traceback: Vec<TraceFrame>,
+    /// Traceback as Python prints it, including chained exceptions
+    pub formatted: Option<String>,
+}
+
+/// A frame of a Python traceback.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceFrame {
+    pub file: String,
+    pub line: Option<u32>,
+    pub function: String,
+    /// Source line of the frame, if the file could be read
+    pub code: Option<String>,
+}
+
+impl DefinitionError {
+    /// An error that did not come from Python, so it has no traceback.
+    pub fn new(kind: impl Into<String>, message: impl Into<String>) -> Self {
+        DefinitionError {
+            kind: kind.into(),
+            message: message.into(),
+            traceback: Vec::new(),
+            formatted: None,
+        }
+    }
+
+    /// Collects the type, message and traceback of a Python exception.
+    pub fn from_pyerr(py: Python<'_>, err: &PyErr) -> Self {
+        let kind = err
+            .get_type(py)
+            .name()
+            .map(|name| name.to_string())
+            .unwrap_or_else(|_| "Exception".to_string());
+        let message = err
+            .value(py)
+            .str()
+            .map(|msg| msg.to_string())
+            .unwrap_or_default();
+
+        DefinitionError {
+            kind,
+            message,
+            traceback: trace_frames(py, err).unwrap_or_default(),
+            formatted: format_exception(py, err).ok(),
+        }
+    }
+
+    /// The innermost frame, where the exception was raised.
+    pub fn location(&self) -> Option<&TraceFrame> {
+        self.traceback.last()
+    }
+
+    pub fn is_assertion(&self) -> bool {
+        self.kind == "AssertionError"
+    }
+
+    /// Whether the error was raised by `nc.fatal`, aborting the run.
+    pub fn is_fatal(&self) -> bool {
+        self.kind == "FatalError"
+    }
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.message.is_empty() {
+            true => write!(f, "{}", self.kind),
+            false => write!(f, "{}: {}", self.kind, self.message),
+        }
+    }
+}
+
+fn trace_frames(py: Python<'_>, err: &PyErr) -> PyResult<Vec<TraceFrame>> {
+    let Some(traceback) = err.traceback(py) else {
+        return Ok(Vec::new());
+    };
+
+    py.import("traceback")?
+        .call_method1("extract_tb", (traceback,))?
+        .try_iter()?
+        .map(|frame| {
+            let frame = frame?;
+            let code = frame
+                .getattr("line")?
+                .extract::<Option<String>>()?
+                .filter(|line| !line.is_empty());
+
+            Ok(TraceFrame {
+                file: frame.getattr("filename")?.extract()?,
+                line: frame.getattr("lineno")?.extract()?,
+                function: frame.getattr("name")?.extract()?,
+                code,
+            })
+        })
+        .collect()
+}
+
+fn format_exception(py: Python<'_>, err: &PyErr) -> PyResult<String> {
+    let lines = py
+        .import("traceback")?
+        .call_method1(
+            "format_exception",
+            (err.get_type(py), err.value(py), err.traceback(py)),
+        )?
+        .extract::<Vec<String>>()?;
+
+    Ok(lines.concat().trim_end().to_string())
+}
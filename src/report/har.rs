@@ -0,0 +1,339 @@
+use super::{RequestRecord, RunReport};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::{collections::HashMap, path::Path};
+
+/// Writes the requests of the report as a HAR file, which browser devtools can open.
+pub fn write_har(report: &RunReport, path: &Path) -> Result<()> {
+    let har = serde_json::to_string_pretty(&to_har(report)).context("Failed to serialize HAR")?;
+
+    std::fs::write(path, har).context(format!("Failed to write HAR to {}", path.display()))
+}
+
+/// Page of the requests sent outside of definitions, not a valid definition name.
+const SCRIPT_PAGE: &str = "[script]";
+
+/// Renders the requests of the report as a HAR 1.2 log.
+///
+/// Every definition that sent requests is a page, named after the definition, which its
+/// requests refer to with `pageref`. Requests sent outside of definitions, by on_init,
+/// on_cleanup and session fixtures, are on the `[script]` page. Requests that failed without a
+/// response have status `0` and the error in `_error`.
+pub fn to_har(report: &RunReport) -> Value {
+    let mut pages = Vec::new();
+    let mut entries = Vec::new();
+
+    let script = (SCRIPT_PAGE, &report.requests);
+    let definitions = report
+        .definitions
+        .iter()
+        .map(|definition| (definition.name.as_str(), &definition.requests));
+    for (page, requests) in std::iter::once(script).chain(definitions) {
+        let Some(first) = requests.iter().map(|r| r.started_at).min() else {
+            continue;
+        };
+
+        pages.push(json!({
+            "startedDateTime": timestamp(first),
+            "id": page,
+            "title": page,
+            "pageTimings": { "onContentLoad": -1, "onLoad": -1 },
+        }));
+        entries.extend(requests.iter().map(|request| entry(page, request)));
+    }
+
+    json!({
+        "log": {
+            "version": "1.2",
+            "creator": { "name": "neocurl", "version": env!("CARGO_PKG_VERSION") },
+            "pages": pages,
+            "entries": entries,
+        }
+    })
+}
+
+fn entry(definition: &str, request: &RequestRecord) -> Value {
+    let mut url = request.url.clone();
+    let mut params: Vec<(&String, &String)> = request.params.iter().collect();
+    params.sort();
+    if !params.is_empty() {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params.iter().copied())
+            .finish();
+        url.push(if url.contains('?') { '&' } else { '?' });
+        url.push_str(&query);
+    }
+
+    let mut har_request = json!({
+        "method": request.method,
+        "url": url,
+        "httpVersion": "HTTP/1.1",
+        "cookies": [],
+        "headers": headers(&request.headers),
+        "queryString": params
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect::<Vec<_>>(),
+        "headersSize": -1,
+        "bodySize": request.body.as_ref().map_or(0, |b| b.len()),
+    });
+    if let Some(body) = &request.body {
+        har_request["postData"] = json!({
+            "mimeType": header(&request.headers, "content-type").unwrap_or_default(),
+            "text": body,
+        });
+    }
+
+    let (response, time) = match &request.response {
+        Some(response) => {
+            let body = response.body.as_deref().unwrap_or_default();
+            let status_text = response.status.split_once(' ').map_or("", |(_, text)| text);
+            let json = json!({
+                "status": response.status_code,
+                "statusText": status_text,
+                "httpVersion": "HTTP/1.1",
+                "cookies": [],
+                "headers": headers(&response.headers),
+                "content": {
+                    "size": body.len(),
+                    "mimeType": header(&response.headers, "content-type")
+                        .unwrap_or("x-unknown"),
+                    "text": body,
+                },
+                "redirectURL": header(&response.headers, "location").unwrap_or_default(),
+                "headersSize": -1,
+                "bodySize": body.len(),
+            });
+            (json, response.duration_ms)
+        }
+        None => {
+            let json = json!({
+                "status": 0,
+                "statusText": "",
+                "httpVersion": "",
+                "cookies": [],
+                "headers": [],
+                "content": { "size": 0, "mimeType": "x-unknown" },
+                "redirectURL": "",
+                "headersSize": -1,
+                "bodySize": -1,
+            });
+            (json, 0)
+        }
+    };
+
+    let mut entry = json!({
+        "pageref": definition,
+        "startedDateTime": timestamp(request.started_at),
+        "time": time,
+        "request": har_request,
+        "response": response,
+        "cache": {},
+        // Only the total duration is measured, it is all spent waiting
+        "timings": {
+            "blocked": -1,
+            "dns": -1,
+            "connect": -1,
+            "ssl": -1,
+            "send": 0,
+            "wait": time,
+            "receive": 0,
+        },
+    });
+    if let Some(error) = &request.error {
+        entry["_error"] = json!(error);
+    }
+
+    entry
+}
+
+fn timestamp(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+/// Headers as HAR name/value pairs, sorted by name.
+fn headers(headers: &HashMap<String, String>) -> Vec<Value> {
+    let mut headers: Vec<(&String, &String)> = headers.iter().collect();
+    headers.sort();
+
+    headers
+        .into_iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect()
+}
+
+fn header<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{Assertions, DefinitionResult, ResponseRecord};
+
+    fn request(url: &str, started_at: &str, response: Option<ResponseRecord>) -> RequestRecord {
+        RequestRecord {
+            started_at: started_at.parse().unwrap(),
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: HashMap::new(),
+            params: HashMap::new(),
+            body: None,
+            response,
+            error: None,
+        }
+    }
+
+    fn ok() -> Option<ResponseRecord> {
+        Some(ResponseRecord {
+            status_code: 200,
+            status: "200 OK".to_string(),
+            headers: HashMap::from([("Content-Type".to_string(), "text/plain".to_string())]),
+            body: Some("hello".to_string()),
+            duration_ms: 12,
+        })
+    }
+
+    fn definition(name: &str, requests: Vec<RequestRecord>) -> DefinitionResult {
+        DefinitionResult {
+            name: name.to_string(),
+            tags: Vec::new(),
+            description: None,
+            passed: true,
+            skipped: None,
+            duration_ms: 20,
+            error: None,
+            assertions: Assertions::default(),
+            requests,
+            logs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn pages() {
+        let mut report = RunReport::new(
+            vec![
+                definition(
+                    "users",
+                    vec![
+                        request("http://a/2", "2025-06-01T12:00:02Z", ok()),
+                        request("http://a/1", "2025-06-01T12:00:01Z", ok()),
+                    ],
+                ),
+                definition("no_requests", Vec::new()),
+                definition(
+                    "orders",
+                    vec![request("http://a/3", "2025-06-01T12:00:03Z", ok())],
+                ),
+            ],
+            100,
+        );
+        report.requests = vec![request("http://a/login", "2025-06-01T12:00:00Z", ok())];
+
+        let har = to_har(&report);
+        let pages: Vec<(&str, &str)> = har["log"]["pages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|page| {
+                assert_eq!(page["id"], page["title"]);
+                (
+                    page["id"].as_str().unwrap(),
+                    page["startedDateTime"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                ("[script]", "2025-06-01T12:00:00.000Z"),
+                ("users", "2025-06-01T12:00:01.000Z"),
+                ("orders", "2025-06-01T12:00:03.000Z"),
+            ]
+        );
+
+        let entries: Vec<(&str, &str)> = har["log"]["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                (
+                    entry["pageref"].as_str().unwrap(),
+                    entry["request"]["url"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("[script]", "http://a/login"),
+                ("users", "http://a/2"),
+                ("users", "http://a/1"),
+                ("orders", "http://a/3"),
+            ]
+        );
+
+        let entry = &har["log"]["entries"][0];
+        assert_eq!(entry["time"], 12);
+        assert_eq!(entry["response"]["status"], 200);
+        assert_eq!(entry["response"]["statusText"], "OK");
+        assert_eq!(entry["response"]["content"]["mimeType"], "text/plain");
+        assert_eq!(entry["response"]["content"]["text"], "hello");
+        assert!(entry.get("_error").is_none());
+    }
+
+    #[test]
+    fn failed_requests() {
+        let mut failed = request("http://a/down", "2025-06-01T12:00:00Z", None);
+        failed.error = Some("Request failed: connection refused".to_string());
+        let report = RunReport::new(vec![definition("down", vec![failed])], 1);
+
+        let har = to_har(&report);
+        let entry = &har["log"]["entries"][0];
+        assert_eq!(entry["pageref"], "down");
+        assert_eq!(entry["time"], 0);
+        assert_eq!(entry["response"]["status"], 0);
+        assert_eq!(entry["response"]["bodySize"], -1);
+        assert_eq!(entry["_error"], "Request failed: connection refused");
+    }
+
+    #[test]
+    fn query_strings() {
+        let mut get = request("http://a/search?q=x", "2025-06-01T12:00:00Z", ok());
+        get.params = HashMap::from([
+            ("sort".to_string(), "name asc".to_string()),
+            ("page".to_string(), "2".to_string()),
+        ]);
+        let mut post = request("http://a/users", "2025-06-01T12:00:00Z", ok());
+        post.method = "POST".to_string();
+        post.params = HashMap::from([("dry".to_string(), "1".to_string())]);
+        post.headers =
+            HashMap::from([("content-type".to_string(), "application/json".to_string())]);
+        post.body = Some("{}".to_string());
+        let report = RunReport::new(vec![definition("search", vec![get, post])], 1);
+
+        let har = to_har(&report);
+        let request = &har["log"]["entries"][0]["request"];
+        assert_eq!(request["url"], "http://a/search?q=x&page=2&sort=name+asc");
+        assert_eq!(
+            request["queryString"],
+            json!([
+                { "name": "page", "value": "2" },
+                { "name": "sort", "value": "name asc" },
+            ])
+        );
+        assert!(request.get("postData").is_none());
+
+        let request = &har["log"]["entries"][1]["request"];
+        assert_eq!(request["method"], "POST");
+        assert_eq!(request["url"], "http://a/users?dry=1");
+        assert_eq!(
+            request["postData"],
+            json!({ "mimeType": "application/json", "text": "{}" })
+        );
+        assert_eq!(request["bodySize"], 2);
+    }
+}
@@ -0,0 +1,167 @@
+use super::{DefinitionResult, RunReport};
+use anyhow::{Context, Result};
+use std::{fmt::Write, path::Path};
+
+/// Writes the report as a JUnit XML file with one `<testcase>` per definition.
+pub fn write_junit(report: &RunReport, suite: &str, path: &Path) -> Result<()> {
+    std::fs::write(path, to_junit(report, suite)).context(format!(
+        "Failed to write JUnit report to {}",
+        path.display()
+    ))
+}
+
+/// Renders the report as JUnit XML.
+pub fn to_junit(report: &RunReport, suite: &str) -> String {
+    let mut xml = String::new();
+    let tests = report.definitions.len();
+    let time = seconds(report.duration_ms);
+
+    // Writing to a String cannot fail
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        xml,
+        r#"<testsuites name="{}" tests="{}" failures="{}" skipped="{}" time="{}">"#,
+        escape(suite),
+        tests,
+        report.failed,
+        report.skipped,
+        time
+    );
+    let _ = writeln!(
+        xml,
+        r#"  <testsuite name="{}" tests="{}" failures="{}" errors="0" skipped="{}" time="{}">"#,
+        escape(suite),
+        tests,
+        report.failed,
+        report.skipped,
+        time
+    );
+
+    for definition in &report.definitions {
+        write_testcase(&mut xml, definition, suite);
+    }
+
+    let _ = writeln!(xml, "  </testsuite>");
+    let _ = writeln!(xml, "</testsuites>");
+
+    xml
+}
+
+fn write_testcase(xml: &mut String, definition: &DefinitionResult, suite: &str) {
+    let _ = writeln!(
+        xml,
+        r#"    <testcase name="{}" classname="{}" assertions="{}" time="{}">"#,
+        escape(&definition.name),
+        escape(suite),
+        definition.assertions.passed + definition.assertions.failed,
+        seconds(definition.duration_ms)
+    );
+
+    if let Some(reason) = &definition.skipped {
+        let _ = writeln!(xml, r#"      <skipped message="{}"/>"#, escape(reason));
+    }
+
+    if let Some(error) = &definition.error {
+        let details = error.formatted.clone().unwrap_or_else(|| error.to_string());
+        let _ = writeln!(
+            xml,
+            r#"      <failure message="{}" type="{}">{}</failure>"#,
+            escape(&error.to_string()),
+            escape(&error.kind),
+            escape(&details)
+        );
+    }
+
+    if !definition.logs.is_empty() {
+        let _ = writeln!(
+            xml,
+            "      <system-out>{}</system-out>",
+            escape(&definition.logs.join("\n"))
+        );
+    }
+
+    let _ = writeln!(xml, "    </testcase>");
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.3}", ms as f64 / 1000.0)
+}
+
+/// Escapes text for use in XML attributes and content, dropping characters XML cannot hold.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' | '\r' | '\t' => escaped.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{Assertions, DefinitionError};
+
+    fn definition(name: &str) -> DefinitionResult {
+        DefinitionResult {
+            name: name.to_string(),
+            tags: Vec::new(),
+            description: None,
+            passed: true,
+            skipped: None,
+            duration_ms: 1500,
+            error: None,
+            assertions: Assertions::default(),
+            requests: Vec::new(),
+            logs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn failed_assertions_are_failures() {
+        let mut failed = definition("get_user");
+        failed.passed = false;
+        failed.assertions = Assertions {
+            passed: 1,
+            failed: 2,
+        };
+        failed.error = Some(DefinitionError::new(
+            "AssertionError",
+            "2 of 3 assertions failed",
+        ));
+        let report = RunReport::new(vec![definition("list_users"), failed], 2000);
+
+        let xml = to_junit(&report, "api");
+
+        assert!(xml.contains(r#"<testsuite name="api" tests="2" failures="1""#));
+        assert!(xml.contains(r#"<testcase name="get_user" classname="api" assertions="3""#));
+        assert!(xml.contains(
+            r#"<failure message="AssertionError: 2 of 3 assertions failed" type="AssertionError">"#
+        ));
+        assert_eq!(xml.matches("<failure").count(), 1);
+    }
+
+    #[test]
+    fn skipped_and_escaped() {
+        let mut skipped = definition("a<b>");
+        skipped.passed = false;
+        skipped.skipped = Some("needs \"prod\"".to_string());
+        let report = RunReport::new(vec![skipped], 0);
+
+        let xml = to_junit(&report, "api");
+
+        assert!(xml.contains(r#"<testcase name="a&lt;b&gt;""#));
+        assert!(xml.contains(r#"<skipped message="needs &quot;prod&quot;"/>"#));
+        assert!(xml.contains(r#"failures="0" errors="0" skipped="1""#));
+    }
+}
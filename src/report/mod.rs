@@ -0,0 +1,330 @@
+//! Structured, machine-readable results of running definitions.
+
+mod error;
+mod har;
+mod junit;
+
+pub use error::{DefinitionError, TraceFrame};
+pub use har::{to_har, write_har};
+pub use junit::{to_junit, write_junit};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Output format of a report.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReportFormat {
+    /// One JSON document written after all definitions finished
+    Json,
+    /// One JSON object per line, written as soon as each definition finishes
+    Ndjson,
+}
+
+/// A request sent by a client while running a definition.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestRecord {
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub method: String,
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    pub params: HashMap<String, String>,
+    pub body: Option<String>,
+    pub response: Option<ResponseRecord>,
+    pub error: Option<String>,
+}
+
+/// A response received for a [`RequestRecord`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResponseRecord {
+    pub status_code: u16,
+    pub status: String,
+    pub headers: HashMap<String, String>,
+    pub body: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Amount of `assert_t`/`assert_f` calls that passed and failed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Assertions {
+    pub passed: u32,
+    pub failed: u32,
+}
+
+/// Outcome of running a single definition.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DefinitionResult {
+    pub name: String,
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+    pub passed: bool,
+    /// Reason the definition was skipped, empty if none was given
+    pub skipped: Option<String>,
+    pub duration_ms: u64,
+    pub error: Option<DefinitionError>,
+    pub assertions: Assertions,
+    pub requests: Vec<RequestRecord>,
+    pub logs: Vec<String>,
+}
+
+/// Outcome of running several definitions.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunReport {
+    pub passed: u32,
+    pub failed: u32,
+    pub skipped: u32,
+    pub duration_ms: u64,
+    pub definitions: Vec<DefinitionResult>,
+    /// Requests sent outside of definitions, by on_init, on_cleanup and session fixtures
+    pub requests: Vec<RequestRecord>,
+}
+
+impl RunReport {
+    pub fn new(definitions: Vec<DefinitionResult>, duration_ms: u64) -> Self {
+        let passed = definitions.iter().filter(|d| d.passed).count() as u32;
+        let skipped = definitions.iter().filter(|d| d.skipped.is_some()).count() as u32;
+        let failed = definitions.len() as u32 - passed - skipped;
+
+        RunReport {
+            passed,
+            failed,
+            skipped,
+            duration_ms,
+            definitions,
+            requests: Vec::new(),
+        }
+    }
+}
+
+/// Writes a report to stdout or a file.
+pub struct ReportWriter {
+    format: ReportFormat,
+    output: Box<dyn Write>,
+}
+
+impl ReportWriter {
+    /// Creates a writer, writing to `path` if given or to stdout otherwise.
+    pub fn new(format: ReportFormat, path: Option<&Path>) -> Result<Self> {
+        let output: Box<dyn Write> = match path {
+            Some(path) => Box::new(
+                std::fs::File::create(path)
+                    .context(format!("Failed to create report file: {}", path.display()))?,
+            ),
+            None => Box::new(std::io::stdout()),
+        };
+
+        Ok(ReportWriter { format, output })
+    }
+
+    /// Records a finished definition. NDJSON reports write it right away.
+    pub fn definition(&mut self, result: &DefinitionResult) -> Result<()> {
+        if self.format == ReportFormat::Ndjson {
+            self.write_line(&NdjsonRecord::Definition(result))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the rest of the report once all definitions finished.
+    pub fn finish(mut self, report: &RunReport) -> Result<()> {
+        match self.format {
+            ReportFormat::Json => self.write_line(report),
+            ReportFormat::Ndjson => self.write_line(&NdjsonRecord::Summary {
+                passed: report.passed,
+                failed: report.failed,
+                skipped: report.skipped,
+                duration_ms: report.duration_ms,
+            }),
+        }
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.output, value).context("Failed to serialize report")?;
+        writeln!(self.output).context("Failed to write report")?;
+        self.output.flush().context("Failed to write report")?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum NdjsonRecord<'a> {
+    Definition(&'a DefinitionResult),
+    Summary {
+        passed: u32,
+        failed: u32,
+        skipped: u32,
+        duration_ms: u64,
+    },
+}
+
+/// CLI arguments selecting a report.
+#[derive(clap::Args, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReportArgs {
+    /// Print a machine-readable report of the results
+    #[clap(long, value_enum)]
+    pub report: Option<ReportFormat>,
+
+    /// Write the report to a file instead of stdout
+    #[clap(long, requires = "report")]
+    pub report_file: Option<PathBuf>,
+}
+
+impl ReportArgs {
+    /// Creates a report writer if a report was requested.
+    pub fn writer(&self) -> Result<Option<ReportWriter>> {
+        self.report
+            .map(|format| ReportWriter::new(format, self.report_file.as_deref()))
+            .transpose()
+    }
+
+    /// Whether the report is printed to stdout, where it must not be mixed with a summary.
+    pub fn to_stdout(&self) -> bool {
+        self.report.is_some() && self.report_file.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn report() -> RunReport {
+        let definition = |name: &str| DefinitionResult {
+            name: name.to_string(),
+            tags: vec!["smoke".to_string()],
+            description: None,
+            passed: true,
+            skipped: None,
+            duration_ms: 5,
+            error: None,
+            assertions: Assertions {
+                passed: 1,
+                failed: 0,
+            },
+            requests: Vec::new(),
+            logs: Vec::new(),
+        };
+
+        let mut failed = definition("create");
+        failed.passed = false;
+        failed.error = Some(DefinitionError::new("AssertionError", "expected 201"));
+        failed.logs = vec!["created".to_string()];
+        let mut skipped = definition("delete");
+        skipped.passed = false;
+        skipped.skipped = Some("dependency `create` failed".to_string());
+
+        RunReport::new(vec![definition("get"), failed, skipped], 15)
+    }
+
+    /// Writes the report like the CLI does and reads back its lines.
+    fn write(format: ReportFormat, report: &RunReport) -> Vec<Value> {
+        let path = std::env::temp_dir().join(format!(
+            "neocurl-report-{:?}-{}.json",
+            format,
+            std::process::id()
+        ));
+        let mut writer = ReportWriter::new(format, Some(&path)).unwrap();
+        for definition in &report.definitions {
+            writer.definition(definition).unwrap();
+        }
+        writer.finish(report).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        text.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn counts() {
+        let report = report();
+        assert_eq!((report.passed, report.failed, report.skipped), (1, 1, 1));
+    }
+
+    #[test]
+    fn ndjson() {
+        let lines = write(ReportFormat::Ndjson, &report());
+
+        assert_eq!(lines.len(), 4);
+        let names: Vec<&Value> = lines[..3].iter().map(|line| &line["name"]).collect();
+        assert_eq!(names, ["get", "create", "delete"]);
+        assert!(lines[..3].iter().all(|line| line["type"] == "definition"));
+        assert_eq!(
+            lines[1],
+            json!({
+                "type": "definition",
+                "name": "create",
+                "tags": ["smoke"],
+                "description": null,
+                "passed": false,
+                "skipped": null,
+                "duration_ms": 5,
+                "error": {
+                    "type": "AssertionError",
+                    "message": "expected 201",
+                    "traceback": [],
+                    "formatted": null,
+                },
+                "assertions": { "passed": 1, "failed": 0 },
+                "requests": [],
+                "logs": ["created"],
+            })
+        );
+        assert_eq!(lines[2]["skipped"], "dependency `create` failed");
+        assert_eq!(
+            lines[3],
+            json!({ "type": "summary", "passed": 1, "failed": 1, "skipped": 1, "duration_ms": 15 })
+        );
+    }
+
+    #[test]
+    fn json() {
+        let lines = write(ReportFormat::Json, &report());
+
+        assert_eq!(lines.len(), 1);
+        let document = &lines[0];
+        let keys: Vec<&String> = document.as_object().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            [
+                "passed",
+                "failed",
+                "skipped",
+                "duration_ms",
+                "definitions",
+                "requests"
+            ]
+        );
+        assert_eq!(
+            (
+                &document["passed"],
+                &document["failed"],
+                &document["skipped"]
+            ),
+            (&json!(1), &json!(1), &json!(1))
+        );
+        assert_eq!(document["definitions"].as_array().unwrap().len(), 3);
+        assert!(document["definitions"][0].get("type").is_none());
+        assert_eq!(document["definitions"][0]["error"], Value::Null);
+        assert_eq!(
+            document["definitions"][1]["error"]["type"],
+            "AssertionError"
+        );
+        assert_eq!(
+            document["definitions"][1]["error"]["message"],
+            "expected 201"
+        );
+        assert_eq!(document["requests"], json!([]));
+
+        // Reports can be read back
+        let read: RunReport = serde_json::from_value(document.clone()).unwrap();
+        assert_eq!(read, report());
+    }
+}
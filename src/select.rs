@@ -0,0 +1,150 @@
+//! Selection of definitions by name, glob, regex and tag.
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// CLI arguments selecting which definitions to run.
+#[derive(clap::Args, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SelectArgs {
+    /// Names or glob patterns (`users_*`) of definitions to run
+    pub names: Vec<String>,
+
+    /// Treat names as regular expressions instead of globs
+    #[clap(long, short)]
+    pub regex: bool,
+
+    /// Only run definitions with this tag, can be repeated
+    #[clap(long = "tag", short = 't')]
+    pub tags: Vec<String>,
+}
+
+/// A compiled selection of definitions.
+pub struct Selection {
+    patterns: Vec<Pattern>,
+    tags: Vec<String>,
+}
+
+enum Pattern {
+    /// A glob also matches its text literally, as `[` starts a character class in globs but
+    /// also appears in case names like `users[admin]`
+    Glob(glob::Pattern),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob.as_str() == name || glob.matches(name),
+            Pattern::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+impl SelectArgs {
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.tags.is_empty()
+    }
+
+    /// Compiles the name patterns.
+    pub fn selection(&self) -> Result<Selection> {
+        let patterns = self
+            .names
+            .iter()
+            .map(|name| {
+                if self.regex {
+                    // Anchored, so a regex selects whole names like a glob does
+                    Regex::new(&format!("^(?:{})$", name))
+                        .map(Pattern::Regex)
+                        .context(format!("Invalid regex: {}", name))
+                } else {
+                    glob::Pattern::new(name)
+                        .map(Pattern::Glob)
+                        .context(format!("Invalid glob pattern: {}", name))
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Selection {
+            patterns,
+            tags: self.tags.clone(),
+        })
+    }
+}
+
+impl Selection {
+    /// Whether a definition is selected. Empty patterns or tags match everything.
+    pub fn matches(&self, name: &str, tags: &[String]) -> bool {
+        let name_matches =
+            self.patterns.is_empty() || self.patterns.iter().any(|p| p.matches(name));
+        let tag_matches = self.tags.is_empty() || self.tags.iter().any(|t| tags.contains(t));
+
+        name_matches && tag_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select(names: &[&str], regex: bool, tags: &[&str]) -> Selection {
+        SelectArgs {
+            names: names.iter().map(|name| name.to_string()).collect(),
+            regex,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+        .selection()
+        .unwrap()
+    }
+
+    #[test]
+    fn names() {
+        let selection = select(&["users"], false, &[]);
+        assert!(selection.matches("users", &[]));
+        assert!(!selection.matches("users_list", &[]));
+    }
+
+    #[test]
+    fn globs() {
+        let selection = select(&["users_*", "orders[admin]"], false, &[]);
+        assert!(selection.matches("users_list", &[]));
+        assert!(!selection.matches("list_users", &[]));
+        assert!(selection.matches("orders[admin]", &[]));
+        assert!(!selection.matches("orders", &[]));
+    }
+
+    #[test]
+    fn regexes_match_whole_names() {
+        let selection = select(&["users_(list|get)"], true, &[]);
+        assert!(selection.matches("users_list", &[]));
+        assert!(selection.matches("users_get", &[]));
+        assert!(!selection.matches("users_list_all", &[]));
+        assert!(!selection.matches("all_users_get", &[]));
+
+        let selection = select(&["a|b"], true, &[]);
+        assert!(selection.matches("b", &[]));
+        assert!(!selection.matches("ab", &[]));
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let args = SelectArgs {
+            names: vec!["users_(".to_string()],
+            regex: true,
+            tags: Vec::new(),
+        };
+        assert!(args.selection().is_err());
+    }
+
+    #[test]
+    fn tags() {
+        let tags = ["smoke".to_string()];
+        let selection = select(&[], false, &["smoke", "slow"]);
+        assert!(selection.matches("users", &tags));
+        assert!(!selection.matches("users", &[]));
+
+        let selection = select(&["users_*"], false, &["smoke"]);
+        assert!(selection.matches("users_list", &tags));
+        assert!(!selection.matches("users_list", &[]));
+        assert!(!selection.matches("orders", &tags));
+    }
+}
@@ -0,0 +1,274 @@
+//! Running ncurl scripts from `cargo test`.
+//!
+//! Run a whole script from a single test:
+//!
+//! ```rust,no_run
+//! #[test]
+//! fn api() {
+//!     neocurl::testing::Script::new("tests/api.py")
+//!         .env("BASE_URL", "http://127.0.0.1:8080")
+//!         .run();
+//! }
+//! ```
+//!
+//! Or get one test per definition from a test target with `harness = false`:
+//!
+//! ```rust,no_run
+//! fn main() -> std::process::ExitCode {
+//!     neocurl::testing::Script::new("tests/api.py").harness()
+//! }
+//! ```
+
+use crate::{DefinitionResult, LogOutput, RunReport, Vm, api::DefinitionMeta, select::SelectArgs};
+use anyhow::{Context, Result};
+use libtest_mimic::{Arguments, Failed, Trial};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+/// VMs share the Python interpreter, so tests using them run one at a time.
+static PYTHON: Mutex<()> = Mutex::new(());
+
+/// An ncurl script to run as tests.
+#[derive(Debug, Clone)]
+pub struct Script {
+    path: PathBuf,
+    env: Vec<(String, String)>,
+    output: LogOutput,
+}
+
+impl Script {
+    /// A script file, or a directory or glob of scripts, see [`VmBuilder::load`](crate::VmBuilder::load).
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Script {
+            path: path.as_ref().to_path_buf(),
+            env: Vec::new(),
+            output: LogOutput::default(),
+        }
+    }
+
+    /// Sets a variable returned by `nc.env`, e.g. the address of a server started by the test.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets where logs are printed, nowhere by default. Failed tests show the logs either way.
+    pub fn output(mut self, output: LogOutput) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Runs all definitions and returns their results, without panicking on failures.
+    pub fn report(&self) -> Result<RunReport> {
+        self.with_vm(|vm| vm.run_tests())
+    }
+
+    /// Runs all definitions, panicking with the failed ones.
+    pub fn run(&self) -> RunReport {
+        let report = self
+            .report()
+            .unwrap_or_else(|e| panic!("Failed to run {}: {:?}", self.path.display(), e));
+        assert_passed(&report);
+
+        report
+    }
+
+    /// Runs the definitions matching `name` and their dependencies, panicking with the failed
+    /// ones. The name of a parametrized definition runs all of its cases.
+    pub fn run_definition(&self, name: &str) -> RunReport {
+        let report = self
+            .with_vm(|vm| {
+                let selection = SelectArgs {
+                    names: vec![name.to_string()],
+                    ..Default::default()
+                }
+                .selection()?;
+                let names = vm.select_definitions(&selection);
+                if names.is_empty() {
+                    return Err(anyhow::anyhow!("Definition not found: {}", name));
+                }
+
+                vm.run_definitions(&names, true, |_| Ok(()))
+            })
+            .unwrap_or_else(|e| panic!("Failed to run {}: {:?}", self.path.display(), e));
+        assert_passed(&report);
+
+        report
+    }
+
+    /// Runs the script as a test harness, reporting every definition as its own test.
+    ///
+    /// Meant as the `main` of a test target with `harness = false`. The usual `cargo test`
+    /// arguments work, e.g. filters and `--list`. The on_init functions run when the first test
+    /// runs, so listing tests sends no requests. Each test runs its definition after the
+    /// definitions it depends on, every definition runs at most once. A test whose definition
+    /// is skipped because a dependency did not pass fails, definitions with `skip` are reported
+    /// as ignored.
+    ///
+    /// Logs are only printed with `--nocapture`, failed tests show them either way.
+    pub fn harness(mut self) -> ExitCode {
+        let args = Arguments::from_args();
+        self.output = match (args.nocapture, self.output) {
+            (true, LogOutput::Silent) => LogOutput::Stdout,
+            (true, output) => output,
+            (false, _) => LogOutput::Silent,
+        };
+
+        let _python = PYTHON.lock().unwrap_or_else(|e| e.into_inner());
+        let vm = self.build_vm().and_then(|mut vm| {
+            vm.load()?;
+            Ok(vm)
+        });
+        let vm = match vm {
+            Ok(vm) => vm,
+            Err(e) => {
+                eprintln!("Failed to load {}: {:?}", self.path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let definitions = vm.definitions();
+        let suite = Arc::new(Suite {
+            vm: Mutex::new(vm),
+            started: OnceLock::new(),
+            results: Mutex::new(Vec::new()),
+            definitions: definitions.clone(),
+        });
+        let trials = definitions
+            .into_iter()
+            .map(|definition| {
+                let suite = suite.clone();
+                let name = definition.name.clone();
+                Trial::test(definition.name, move || suite.check(&name))
+                    .with_ignored_flag(definition.skip.is_some())
+            })
+            .collect();
+
+        let conclusion = libtest_mimic::run(&args, trials);
+
+        // Nothing to clean up if no test ran, e.g. with --list
+        if suite.started.get().is_some()
+            && let Err(e) = suite.vm.lock().unwrap().cleanup()
+        {
+            eprintln!("Failed to clean up {}: {:?}", self.path.display(), e);
+            return ExitCode::FAILURE;
+        }
+
+        conclusion.exit_code()
+    }
+
+    fn build_vm(&self) -> Result<Vm> {
+        let mut builder = Vm::builder()
+            .load(&self.path)
+            .context("Failed to load script")?
+            .output(self.output);
+        for (key, value) in &self.env {
+            builder = builder.env(key, value);
+        }
+
+        builder.build()
+    }
+
+    /// Runs `f` on an initialized VM and cleans it up afterwards, also if `f` failed.
+    fn with_vm<R>(&self, f: impl FnOnce(&Vm) -> Result<R>) -> Result<R> {
+        let _python = PYTHON.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut vm = self.build_vm()?;
+        let result = vm.init().and_then(|_| f(&vm));
+        let cleanup = vm.cleanup().context("Failed to clean up");
+
+        let result = result?;
+        cleanup?;
+
+        Ok(result)
+    }
+}
+
+/// A script shared by the tests of a harness, started when the first test runs.
+struct Suite {
+    vm: Mutex<Vm>,
+    /// Outcome of the on_init functions
+    started: OnceLock<Result<(), String>>,
+    /// Results of the definitions that ran so far
+    results: Mutex<Vec<DefinitionResult>>,
+    definitions: Vec<DefinitionMeta>,
+}
+
+impl Suite {
+    /// Runs a definition after its dependencies, unless they already ran for other tests.
+    fn check(&self, name: &str) -> Result<(), Failed> {
+        let vm = self.vm.lock().unwrap();
+        self.started
+            .get_or_init(|| vm.start().map_err(|e| format!("{:?}", e)))
+            .as_ref()
+            .map_err(|e| Failed::from(e.as_str()))?;
+
+        let mut results = self.results.lock().unwrap();
+        vm.run_pending(&[name.to_string()], true, &mut results, |_| Ok(()))
+            .map_err(|e| Failed::from(format!("{:?}", e)))?;
+
+        let result = results
+            .iter()
+            .find(|result| result.name == name)
+            .ok_or_else(|| Failed::from(format!("Definition did not run: {}", name)))?;
+
+        let skipped_itself = self
+            .definitions
+            .iter()
+            .any(|definition| definition.name == name && definition.skip.is_some());
+        match (&result.skipped, skipped_itself) {
+            // Run with --include-ignored
+            (Some(_), true) => Ok(()),
+            (Some(reason), false) => Err(Failed::from(format!("Skipped: {}", reason))),
+            (None, _) if is_failure(result) => Err(Failed::from(failure_message(result))),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+/// Skipped definitions do not fail, like in `ncurl test`. A definition skipped because of a
+/// failed dependency is covered by the failure of the dependency.
+fn is_failure(result: &DefinitionResult) -> bool {
+    !result.passed && result.skipped.is_none()
+}
+
+/// Panics listing every failed definition of the report.
+fn assert_passed(report: &RunReport) {
+    let failed: Vec<&DefinitionResult> = report
+        .definitions
+        .iter()
+        .filter(|result| is_failure(result))
+        .collect();
+    if failed.is_empty() {
+        return;
+    }
+
+    let failures: Vec<String> = failed
+        .iter()
+        .map(|result| format!("---- {} ----\n{}", result.name, failure_message(result)))
+        .collect();
+    panic!(
+        "{} of {} definitions failed\n\n{}",
+        failed.len(),
+        report.definitions.len(),
+        failures.join("\n\n")
+    );
+}
+
+/// Describes why a definition did not pass, with the traceback and logs.
+fn failure_message(result: &DefinitionResult) -> String {
+    let mut message = match &result.error {
+        Some(error) => error.formatted.clone().unwrap_or_else(|| error.to_string()),
+        None => "Failed".to_string(),
+    };
+
+    if !result.logs.is_empty() {
+        message.push_str("\n\nLogs:\n");
+        message.push_str(&result.logs.join("\n"));
+    }
+
+    message
+}
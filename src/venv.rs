@@ -0,0 +1,351 @@
+//! Finding the virtual environment whose libraries a script imports.
+
+use anyhow::{Result, bail};
+use std::{
+    ffi::OsString,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Directory names of a venv next to a script, as created by `python -m venv`, uv and poetry.
+const VENV_DIRS: [&str; 2] = [".venv", "venv"];
+
+/// Major and minor version of a Python interpreter.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PythonVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl PythonVersion {
+    /// Parses the major and minor version from versions like `3.12`, `3.12.1` or
+    /// `3.12.1.final.0`.
+    fn parse(version: &str) -> Option<Self> {
+        let mut parts = version.trim().split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+
+        Some(PythonVersion { major, minor })
+    }
+}
+
+impl fmt::Display for PythonVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// A virtual environment and the site-packages directory to load libraries from.
+#[derive(Debug)]
+pub struct Venv {
+    pub root: PathBuf,
+    pub site_packages: PathBuf,
+}
+
+/// Finds the venv to use with an interpreter of `python` version.
+///
+/// Tries, in order, the active venv (`VIRTUAL_ENV`, set by `source venv/bin/activate`,
+/// `poetry shell` and `uv run`), the active conda environment other than `base`, and a `.venv` or
+/// `venv` directory in the directory of the script or any of its parents.
+///
+/// Fails if the venv was created with a different Python version, its libraries could not be
+/// imported.
+pub fn find(script_dir: &Path, python: PythonVersion) -> Result<Option<Venv>> {
+    let Some(root) = locate(script_dir, |name| std::env::var_os(name)) else {
+        return Ok(None);
+    };
+
+    let site_packages = site_packages(&root, python)?;
+
+    Ok(Some(Venv {
+        root,
+        site_packages,
+    }))
+}
+
+/// Returns the root of the first venv found, reading environment variables with `var`.
+fn locate(script_dir: &Path, var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    if let Some(venv) = var("VIRTUAL_ENV").filter(|v| !v.is_empty()) {
+        let venv = PathBuf::from(venv);
+        if venv.is_dir() {
+            tracing::debug!("Using venv from VIRTUAL_ENV: {}", venv.display());
+            return Some(venv);
+        }
+        tracing::warn!(
+            "VIRTUAL_ENV points to {}, which does not exist",
+            venv.display()
+        );
+    }
+
+    // The base environment is often activated by default and is not specific to the script
+    let conda_env = var("CONDA_DEFAULT_ENV").unwrap_or_default();
+    if let Some(prefix) = var("CONDA_PREFIX").filter(|p| !p.is_empty())
+        && conda_env != "base"
+    {
+        let prefix = PathBuf::from(prefix);
+        if prefix.is_dir() {
+            tracing::debug!("Using conda environment: {}", prefix.display());
+            return Some(prefix);
+        }
+    }
+
+    let script_dir = script_dir
+        .canonicalize()
+        .unwrap_or_else(|_| script_dir.to_path_buf());
+    for dir in script_dir.ancestors() {
+        for name in VENV_DIRS {
+            let venv = dir.join(name);
+            if venv.join("pyvenv.cfg").is_file() {
+                tracing::debug!("Using venv next to the script: {}", venv.display());
+                return Some(venv);
+            }
+        }
+    }
+
+    None
+}
+
+/// Finds the site-packages directory of the venv at `root`, checking that it was created for
+/// the `python` version.
+fn site_packages(root: &Path, python: PythonVersion) -> Result<PathBuf> {
+    if let Some(version) = cfg_version(root)
+        && version != python
+    {
+        return Err(version_mismatch(root, version, python));
+    }
+
+    // Windows venvs and conda environments are not versioned
+    let windows = root.join("Lib").join("site-packages");
+    if windows.is_dir() {
+        return Ok(windows);
+    }
+
+    for lib in ["lib", "lib64"] {
+        let site_packages = root
+            .join(lib)
+            .join(format!("python{}", python))
+            .join("site-packages");
+        if site_packages.is_dir() {
+            return Ok(site_packages);
+        }
+    }
+
+    // Conda environments have no pyvenv.cfg, the version is only in the lib directory
+    if let Some(version) = lib_versions(root).into_iter().find(|v| *v != python) {
+        return Err(version_mismatch(root, version, python));
+    }
+
+    bail!(
+        "No site-packages directory for Python {} found in the virtual environment {}",
+        python,
+        root.display()
+    )
+}
+
+/// Reads the Python version from the `pyvenv.cfg` of a venv.
+///
+/// `python -m venv` writes it as `version`, uv and virtualenv (used by poetry) as `version_info`.
+fn cfg_version(root: &Path) -> Option<PythonVersion> {
+    let cfg = std::fs::read_to_string(root.join("pyvenv.cfg")).ok()?;
+
+    cfg.lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| matches!(key.trim(), "version" | "version_info"))
+        .and_then(|(_, value)| PythonVersion::parse(value))
+}
+
+/// Versions of the `lib/pythonX.Y` directories of a venv.
+fn lib_versions(root: &Path) -> Vec<PythonVersion> {
+    let Ok(entries) = std::fs::read_dir(root.join("lib")) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            PythonVersion::parse(name.to_str()?.strip_prefix("python")?)
+        })
+        .collect()
+}
+
+fn version_mismatch(root: &Path, venv: PythonVersion, python: PythonVersion) -> anyhow::Error {
+    anyhow::anyhow!(
+        "The virtual environment {} uses Python {}, but neocurl was built with Python {}. \
+        Recreate it with `python{} -m venv` or `uv venv --python {}`",
+        root.display(),
+        venv,
+        python,
+        python,
+        python
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PYTHON: PythonVersion = PythonVersion {
+        major: 3,
+        minor: 12,
+    };
+
+    /// A directory removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("neocurl-venv-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir.canonicalize().unwrap())
+        }
+
+        /// Creates a fake venv at `path`, with a `pyvenv.cfg` and a site-packages directory.
+        fn venv(&self, path: &str, cfg: &str, lib: &str) -> PathBuf {
+            let root = self.0.join(path);
+            std::fs::create_dir_all(root.join(lib).join("site-packages")).unwrap();
+            std::fs::write(root.join("pyvenv.cfg"), cfg).unwrap();
+            root
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn no_env(_: &str) -> Option<OsString> {
+        None
+    }
+
+    #[test]
+    fn parse_versions() {
+        let version = |major, minor| Some(PythonVersion { major, minor });
+        assert_eq!(PythonVersion::parse("3.12"), version(3, 12));
+        assert_eq!(PythonVersion::parse(" 3.12.1\n"), version(3, 12));
+        assert_eq!(PythonVersion::parse("3.9.18.final.0"), version(3, 9));
+        assert_eq!(PythonVersion::parse("3"), None);
+        assert_eq!(PythonVersion::parse("three.12"), None);
+        assert_eq!(PYTHON.to_string(), "3.12");
+    }
+
+    #[test]
+    fn cfg_versions() {
+        let dir = TempDir::new("cfg");
+        let venv = dir.venv(
+            "venv",
+            "home = /usr/bin\nversion = 3.12.1\n",
+            "lib/python3.12",
+        );
+        assert_eq!(cfg_version(&venv), Some(PYTHON));
+
+        let uv = dir.venv(
+            "uv",
+            "home = /usr/bin\nversion_info = 3.11.9\n",
+            "lib/python3.11",
+        );
+        assert_eq!(
+            cfg_version(&uv),
+            Some(PythonVersion {
+                major: 3,
+                minor: 11
+            })
+        );
+
+        let unknown = dir.venv("unknown", "home = /usr/bin\n", "lib/python3.12");
+        assert_eq!(cfg_version(&unknown), None);
+        assert_eq!(
+            site_packages(&unknown, PYTHON).unwrap(),
+            unknown.join("lib/python3.12/site-packages")
+        );
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let dir = TempDir::new("mismatch");
+        let venv = dir.venv(".venv", "version_info = 3.11.9\n", "lib/python3.11");
+
+        let error = site_packages(&venv, PYTHON).unwrap_err().to_string();
+        assert!(error.contains("uses Python 3.11, but neocurl was built with Python 3.12"));
+        assert!(error.contains("uv venv --python 3.12"));
+
+        // Without a version in pyvenv.cfg, the lib directory tells
+        let conda = dir.venv("conda", "", "lib/python3.10");
+        let error = site_packages(&conda, PYTHON).unwrap_err().to_string();
+        assert!(error.contains("uses Python 3.10"));
+
+        let empty = dir.venv("empty", "", "bin");
+        let error = site_packages(&empty, PYTHON).unwrap_err().to_string();
+        assert!(error.starts_with("No site-packages directory for Python 3.12"));
+    }
+
+    #[test]
+    fn site_packages_dirs() {
+        let dir = TempDir::new("site");
+        let unix = dir.venv("unix", "version = 3.12.1", "lib64/python3.12");
+        assert_eq!(
+            site_packages(&unix, PYTHON).unwrap(),
+            unix.join("lib64/python3.12/site-packages")
+        );
+
+        let windows = dir.venv("windows", "version = 3.12.1", "Lib");
+        assert_eq!(
+            site_packages(&windows, PYTHON).unwrap(),
+            windows.join("Lib/site-packages")
+        );
+    }
+
+    #[test]
+    fn venv_in_ancestor() {
+        let dir = TempDir::new("ancestor");
+        let venv = dir.venv(".venv", "version = 3.12.1", "lib/python3.12");
+        let scripts = dir.0.join("api/scripts");
+        std::fs::create_dir_all(&scripts).unwrap();
+
+        assert_eq!(locate(&scripts, no_env), Some(venv.clone()));
+
+        // A venv closer to the script wins
+        let closer = dir.venv("api/venv", "version = 3.12.1", "lib/python3.12");
+        assert_eq!(locate(&scripts, no_env), Some(closer));
+        assert_eq!(locate(&dir.0, no_env), Some(venv));
+    }
+
+    #[test]
+    fn env_precedence() {
+        let dir = TempDir::new("env");
+        dir.venv(".venv", "version = 3.12.1", "lib/python3.12");
+        let active = dir.venv("active", "version = 3.12.1", "lib/python3.12");
+        let conda = dir.venv("conda", "", "lib/python3.12");
+
+        let env = |vars: Vec<(&'static str, PathBuf)>| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.clone().into_os_string())
+            }
+        };
+
+        let vars = vec![
+            ("VIRTUAL_ENV", active.clone()),
+            ("CONDA_PREFIX", conda.clone()),
+        ];
+        assert_eq!(locate(&dir.0, env(vars)), Some(active.clone()));
+
+        let vars = vec![("CONDA_PREFIX", conda.clone())];
+        assert_eq!(locate(&dir.0, env(vars)), Some(conda.clone()));
+
+        // The base conda environment is not specific to the script
+        let vars = vec![
+            ("CONDA_PREFIX", conda),
+            ("CONDA_DEFAULT_ENV", PathBuf::from("base")),
+        ];
+        assert_eq!(locate(&dir.0, env(vars)), Some(dir.0.join(".venv")));
+
+        // A VIRTUAL_ENV that does not exist is ignored
+        let vars = vec![("VIRTUAL_ENV", dir.0.join("missing"))];
+        assert_eq!(locate(&dir.0, env(vars)), Some(dir.0.join(".venv")));
+    }
+}
@@ -1,11 +1,25 @@
 use anyhow::{Context, Result};
-use pyo3::{ffi::c_str, prelude::*, types::PyAnyMethods, Python};
-use std::{ffi::CString, path::PathBuf};
-
-use crate::api::{CALLS, TESTS};
+use pyo3::{Python, prelude::*, types::PyAnyMethods};
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    api::{DefinitionMeta, FixtureResolver, LogOutput, PrintCurl, PyLogLevel, VmState},
+    report::{Assertions, DefinitionError, DefinitionResult, RequestRecord, RunReport},
+    select::Selection,
+    venv::{self, PythonVersion},
+};
 
 pub struct VmBuilder {
-    loaded: Option<(PathBuf, String)>,
+    loaded: Option<Vec<ScriptFile>>,
+    output: LogOutput,
+    print_curl: PrintCurl,
+    dry_run: bool,
+    env: HashMap<String, String>,
 }
 
 impl Default for VmBuilder {
@@ -15,46 +29,131 @@ impl Default for VmBuilder {
 }
 
 impl VmBuilder {
-    /// Adds a source code file to the VM builder.
+    /// Adds source code to the VM builder: a file, a directory or a glob (`tests/*.py`).
+    ///
+    /// A directory loads all of its `.py` files not starting with `_`. When several files are
+    /// loaded, their definitions are namespaced by file name, `users::create`.
     #[tracing::instrument(skip_all, fields(source))]
-    pub fn load(mut self, source: String) -> Result<Self> {
+    pub fn load(mut self, source: impl AsRef<Path>) -> Result<Self> {
         if self.loaded.is_some() {
             tracing::warn!("Overwriting previously loaded source code file");
         }
 
-        let content = read_file(source).context("Failed to read source file")?;
-        let source = content.trim().to_string();
+        let paths = script_paths(source.as_ref())?;
+        let namespaced = paths.len() > 1;
+
+        let mut files: Vec<ScriptFile> = Vec::new();
+        for path in paths {
+            // Kept as is, so line numbers in tracebacks match the file
+            let source = read_file(&path).context("Failed to read source file")?;
+            if source.trim().is_empty() && !namespaced {
+                return Err(anyhow::anyhow!("Source code file is empty"));
+            }
+
+            let namespace = match namespaced {
+                true => Some(file_stem(&path)),
+                false => None,
+            };
+            if let Some(other) = files
+                .iter()
+                .find(|f| namespace.is_some() && f.namespace == namespace)
+            {
+                return Err(anyhow::anyhow!(
+                    "{} and {} have the same name, definitions are namespaced by file name",
+                    other.path.display(),
+                    path.display()
+                ));
+            }
 
-        if !source.is_empty() {
-            let path = PathBuf::from(source.clone());
-            self.loaded = Some((path, source));
-        } else {
-            return Err(anyhow::anyhow!("Source code file is empty"));
+            files.push(ScriptFile {
+                path,
+                source,
+                namespace,
+                module: None,
+            });
         }
+        self.loaded = Some(files);
 
         Ok(self)
     }
 
+    /// Sets where log lines are printed, nowhere by default.
+    ///
+    /// Logs are captured in the results of definitions regardless of the output.
+    pub fn output(mut self, output: LogOutput) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Sets whether requests are printed as curl commands when they are sent.
+    pub fn print_curl(mut self, print_curl: PrintCurl) -> Self {
+        self.print_curl = print_curl;
+        self
+    }
+
+    /// Sets whether requests are answered by the stubs of the script instead of being sent.
+    ///
+    /// Requests are printed as curl commands, redacted if set with [`VmBuilder::print_curl`].
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets a variable returned by `nc.env`, taking precedence over the environment.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
     pub fn new() -> Self {
-        VmBuilder { loaded: None }
+        VmBuilder {
+            loaded: None,
+            output: LogOutput::default(),
+            print_curl: PrintCurl::default(),
+            dry_run: false,
+            env: HashMap::new(),
+        }
     }
 
     pub fn build(self) -> Result<Vm> {
         if self.loaded.is_none() {
             return Err(anyhow::anyhow!("No source code file loaded"));
         }
-        let (path, source) = self.loaded.unwrap();
+        let files = self.loaded.unwrap();
+
+        let state = VmState::default();
+        state.logger_config.lock().unwrap().output = self.output;
+        *state.print_curl.lock().unwrap() = self.print_curl;
+        *state.dry_run.lock().unwrap() = self.dry_run;
+        *state.env.lock().unwrap() = self.env;
+        *state.script_dir.lock().unwrap() = script_dir(&files[0].path).to_path_buf();
 
         Ok(Vm {
-            source,
-            _path: path,
+            files,
+            neocurl: None,
+            state: Arc::new(state),
         })
     }
 }
 
-pub struct Vm {
+/// A file of the loaded script.
+struct ScriptFile {
+    path: PathBuf,
     source: String,
-    _path: PathBuf,
+    /// Prefix of the names of definitions in the file, set when several files are loaded
+    namespace: Option<String>,
+    module: Option<Py<PyModule>>,
+}
+
+/// A loaded script with its own `neocurl` module and state.
+///
+/// Running definitions returns their results instead of printing a summary, logs are printed
+/// as configured with [`VmBuilder::output`].
+pub struct Vm {
+    files: Vec<ScriptFile>,
+    /// The `neocurl` module of this VM, holding its state
+    neocurl: Option<Py<PyModule>>,
+    state: Arc<VmState>,
 }
 
 impl Vm {
@@ -62,7 +161,39 @@ impl Vm {
         VmBuilder::new()
     }
 
-    pub fn init(&self) -> Result<()> {
+    /// Paths of the loaded script files.
+    pub fn paths(&self) -> Vec<&Path> {
+        self.files.iter().map(|file| file.path.as_path()).collect()
+    }
+
+    /// Definitions, hooks, counters and logger config of this VM.
+    pub fn state(&self) -> &Arc<VmState> {
+        &self.state
+    }
+
+    /// Runs `f` with the GIL held and this VM's `neocurl` module imported.
+    ///
+    /// Several VMs share the interpreter, so each one puts its module in `sys.modules` before
+    /// running Python code.
+    fn with_python<R>(&self, f: impl FnOnce(Python<'_>) -> Result<R>) -> Result<R> {
+        Python::with_gil(|py| {
+            if let Some(neocurl) = &self.neocurl {
+                py.import("sys")?
+                    .getattr("modules")?
+                    .set_item("neocurl", neocurl)?;
+            }
+
+            f(py)
+        })
+    }
+
+    pub fn init(&mut self) -> Result<()> {
+        self.load()?;
+        self.start()
+    }
+
+    /// Loads the script, registering its definitions and hooks without running on_init.
+    pub(crate) fn load(&mut self) -> Result<()> {
         Python::with_gil(|py| -> Result<()> {
             self.load_venv_libs(py)
                 .context("Failed to load virtual environment libraries")?;
@@ -71,116 +202,482 @@ impl Vm {
             Ok(())
         })?;
 
-        Python::with_gil(|py| -> Result<()> {
-            let _ = self
-                .create_module_from_code(py)
-                .context("Failed to create module from source code")?;
-            self.run_on_init(py)?;
+        self.load_script().context("Failed to load script")
+    }
 
-            Ok(())
-        })?;
+    /// Runs the on_init functions of the loaded script.
+    pub(crate) fn start(&self) -> Result<()> {
+        self.with_python(|py| self.run_on_init(py))
+            .context("Failed to load script")
+    }
 
-        Ok(())
+    /// Re-reads the script from disk, replacing all registered definitions and hooks.
+    ///
+    /// The on_cleanup functions and session fixture teardowns of the previous load run first.
+    pub fn reload(&mut self) -> Result<()> {
+        let namespaced = self.files.len() > 1;
+        for file in &mut self.files {
+            let source = read_file(&file.path).context("Failed to read source file")?;
+            if source.trim().is_empty() && !namespaced {
+                return Err(anyhow::anyhow!("Source code file is empty"));
+            }
+            file.source = source;
+        }
+
+        // The previous load cleans up after itself before its hooks are replaced
+        let cleanup = self.cleanup();
+        self.state.clear_registrations();
+        let load = self
+            .load_script()
+            .context("Failed to load script")
+            .and_then(|_| self.start());
+
+        cleanup.and(load)
+    }
+
+    /// Evaluates a line of Python in the namespace of the loaded script, the first file if
+    /// several are loaded.
+    ///
+    /// Expressions return their `repr`, statements return `None`.
+    pub fn eval(&self, line: &str) -> Result<Option<String>> {
+        let module = self
+            .files
+            .first()
+            .and_then(|file| file.module.as_ref())
+            .ok_or_else(|| anyhow::anyhow!("No script loaded"))?;
+
+        self.with_python(|py| -> Result<Option<String>> {
+            let globals = module.bind(py).dict();
+            if !globals.contains("neocurl")? {
+                globals.set_item("neocurl", py.import("neocurl")?)?;
+            }
+
+            let code = CString::new(line)?;
+            match py.eval(code.as_c_str(), Some(&globals), None) {
+                Ok(value) if value.is_none() => Ok(None),
+                Ok(value) => Ok(Some(value.repr()?.to_string())),
+                Err(e) if e.is_instance_of::<pyo3::exceptions::PySyntaxError>(py) => {
+                    py.run(code.as_c_str(), Some(&globals), None)?;
+                    Ok(None)
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
     }
 
+    /// Tears down session fixtures and runs the on_cleanup functions.
+    ///
+    /// Everything runs even if something fails, the first error is returned.
     pub fn cleanup(&self) -> Result<()> {
-        Python::with_gil(|py| -> Result<()> {
-            self.run_on_cleanup(py)
-                .context("Failed to run on_cleanup function")?;
+        self.with_python(|py| -> Result<()> {
+            let teardown = self
+                .state
+                .session_fixtures
+                .lock()
+                .unwrap()
+                .teardown(py)
+                .context("Failed to tear down session fixtures");
+            let on_cleanup = self
+                .run_on_cleanup(py)
+                .context("Failed to run on_cleanup functions");
 
-            Ok(())
+            teardown.and(on_cleanup)
         })
     }
 
-    pub fn run_definition(&self, name: String, test_mode: bool) -> Result<()> {
-        Python::with_gil(|py| {
-            for def in crate::api::REGISTRY.lock().unwrap().iter() {
-                let def_name = def.getattr(py, "__name__")?.extract::<String>(py)?;
-
-                if def_name == name {
-                    tracing::debug!("Running definition: {}", name);
-                    super::api::LOGGER_CONFIG
-                        .lock()
-                        .unwrap()
-                        .set_context(name.clone());
-
-                    let client = Py::new(py, super::api::PyClient {})?;
-                    let res = def.call1(py, (client,));
-
-                    if let Err(e) = res {
-                        if test_mode {
-                            TESTS.lock().unwrap().1 += 1;
-                        }
-                        CALLS.lock().unwrap().1 += 1;
-
-                        let code: CString =
-                            CString::new(format!("import neocurl\nneocurl.error(\"{}\")", e))?;
-                        py.run(code.as_c_str(), None, None).context(format!(
-                            "Failed to run error code for definition: {}",
-                            name
-                        ))?;
-                    } else {
-                        if test_mode {
-                            TESTS.lock().unwrap().0 += 1;
-                        }
-                        CALLS.lock().unwrap().0 += 1;
+    /// Runs a definition after the definitions it depends on, returning its result.
+    pub fn run_definition(&self, name: &str, test_mode: bool) -> Result<DefinitionResult> {
+        let report = self.run_definitions(&[name.to_string()], test_mode, |_| Ok(()))?;
+
+        report
+            .definitions
+            .into_iter()
+            .find(|result| result.name == name)
+            .ok_or_else(|| anyhow::anyhow!("Definition not found: {}", name))
+    }
+
+    /// Runs a single definition, or skips it if `skip` or its own skip option is set.
+    fn execute_definition(
+        &self,
+        name: String,
+        test_mode: bool,
+        skip: Option<String>,
+    ) -> Result<DefinitionResult> {
+        self.with_python(|py| {
+            let state = &self.state;
+            let (func, case, meta) = state
+                .registry
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|def| def.meta.name == name)
+                .map(|def| {
+                    (
+                        def.func.clone_ref(py),
+                        def.case.as_ref().map(|case| case.clone_ref(py)),
+                        def.meta.clone(),
+                    )
+                })
+                .ok_or_else(|| anyhow::anyhow!("Definition not found: {}", name))?;
+
+            state
+                .logger_config
+                .lock()
+                .unwrap()
+                .set_context(name.clone());
+
+            let assertions_before = *state.assertions.lock().unwrap();
+            let outside = std::mem::take(&mut *state.requests.lock().unwrap());
+            state.script_requests.lock().unwrap().extend(outside);
+            state.violations.lock().unwrap().clear();
+            state.logs.lock().unwrap().clear();
+
+            let mut result = DefinitionResult {
+                name: name.clone(),
+                tags: meta.tags.clone(),
+                description: meta.description.clone(),
+                passed: false,
+                skipped: None,
+                duration_ms: 0,
+                error: None,
+                assertions: Assertions::default(),
+                requests: Vec::new(),
+                logs: Vec::new(),
+            };
+
+            if let Some(reason) = skip.as_ref().or(meta.skip.as_ref()) {
+                tracing::debug!("Skipping definition: {}", name);
+                let msg = match reason.is_empty() {
+                    true => "Skipped".to_string(),
+                    false => format!("Skipped: {}", reason),
+                };
+                crate::api::write_log(state, PyLogLevel::Warn, msg)?;
+
+                state.logger_config.lock().unwrap().clear_context();
+                result.skipped = Some(reason.clone());
+                result.logs = std::mem::take(&mut *state.logs.lock().unwrap());
+                return Ok(result);
+            }
+
+            tracing::debug!("Running definition: {}", name);
+
+            let start = std::time::Instant::now();
+            let client = Py::new(py, super::api::PyClient::new(state.clone(), meta.timeout)?)?;
+            let fixtures = FixtureResolver::new(state.clone(), client.into_any(), case);
+            let res = self.call_with_hooks(py, &func, fixtures);
+            let duration = start.elapsed();
+
+            let error = match (res, meta.timeout) {
+                (Err(e), _) => Some(DefinitionError::from_pyerr(py, &e)),
+                (Ok(_), Some(timeout)) if duration.as_millis() > timeout as u128 => {
+                    Some(DefinitionError::new(
+                        "TimeoutError",
+                        format!(
+                            "Definition exceeded its timeout of {}ms (took {}ms)",
+                            timeout,
+                            duration.as_millis()
+                        ),
+                    ))
+                }
+                (Ok(_), _) => None,
+            };
+
+            let assertions_after = *state.assertions.lock().unwrap();
+            let assertions = Assertions {
+                passed: assertions_after.passed - assertions_before.passed,
+                failed: assertions_after.failed - assertions_before.failed,
+            };
+            // A failed nc.assert_t or nc.assert_f fails the definition, even if it went on
+            let error = error.or_else(|| {
+                (assertions.failed > 0).then(|| {
+                    DefinitionError::new(
+                        "AssertionError",
+                        format!(
+                            "{} of {} assertions failed",
+                            assertions.failed,
+                            assertions.passed + assertions.failed
+                        ),
+                    )
+                })
+            });
+
+            let violations = std::mem::take(&mut *state.violations.lock().unwrap());
+            let error = match (error, violations.is_empty()) {
+                (None, false) => Some(DefinitionError::new(
+                    crate::api::VIOLATION_KIND,
+                    format!(
+                        "Responses do not match the OpenAPI spec:\n{}",
+                        violations.join("\n")
+                    ),
+                )),
+                (error, _) => {
+                    for violation in violations {
+                        crate::api::write_log(state, PyLogLevel::Error, violation)?;
                     }
+                    error
+                }
+            };
 
-                    super::api::LOGGER_CONFIG.lock().unwrap().clear_context();
+            if let Some(e) = &error {
+                if test_mode {
+                    state.tests.lock().unwrap().1 += 1;
+                }
+                state.calls.lock().unwrap().1 += 1;
 
-                    return Ok(());
+                crate::api::write_error(state, e)?;
+            } else {
+                if test_mode {
+                    state.tests.lock().unwrap().0 += 1;
                 }
+                state.calls.lock().unwrap().0 += 1;
             }
 
-            Err(anyhow::anyhow!("Definition not found: {}", name))
+            state.logger_config.lock().unwrap().clear_context();
+
+            result.passed = error.is_none();
+            result.duration_ms = duration.as_millis() as u64;
+            result.error = error;
+            result.assertions = assertions;
+            result.requests = std::mem::take(&mut *state.requests.lock().unwrap());
+            result.logs = std::mem::take(&mut *state.logs.lock().unwrap());
+
+            Ok(result)
         })
     }
 
-    pub fn run_tests(&self) -> Result<()> {
-        let definitions = self.list_definitions();
+    /// Calls a definition between the before_each and after_each hooks, with its fixtures.
+    ///
+    /// after_each hooks and fixture teardowns run even if the definition fails. The first
+    /// error is returned.
+    fn call_with_hooks(
+        &self,
+        py: Python<'_>,
+        func: &Py<PyAny>,
+        mut fixtures: FixtureResolver,
+    ) -> PyResult<()> {
+        let before_each: Vec<Py<PyAny>> = self
+            .state
+            .before_each
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|hook| hook.clone_ref(py))
+            .collect();
+        let after_each: Vec<Py<PyAny>> = self
+            .state
+            .after_each
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|hook| hook.clone_ref(py))
+            .collect();
+
+        let mut result = before_each
+            .iter()
+            .try_for_each(|hook| fixtures.call(py, hook).map(|_| ()))
+            .and_then(|_| fixtures.call(py, func).map(|_| ()));
+
+        for hook in &after_each {
+            let res = fixtures.call(py, hook).map(|_| ());
+            if result.is_ok() {
+                result = res;
+            }
+        }
+
+        let res = fixtures.teardown(py);
+        result.and(res)
+    }
+
+    /// Runs the given definitions and their dependencies, calling `on_result` as soon as each
+    /// one finishes.
+    ///
+    /// Dependencies run first. A definition whose dependency did not pass is skipped. After a
+    /// definition calls `nc.fatal`, all remaining definitions are skipped.
+    pub fn run_definitions(
+        &self,
+        names: &[String],
+        test_mode: bool,
+        on_result: impl FnMut(&DefinitionResult) -> Result<()>,
+    ) -> Result<RunReport> {
+        let start = std::time::Instant::now();
+        let mut results = Vec::new();
+        self.run_pending(names, test_mode, &mut results, on_result)?;
+
+        let mut report = RunReport::new(results, start.elapsed().as_millis() as u64);
+        report.requests = self.take_requests();
+
+        Ok(report)
+    }
+
+    /// Like [`Vm::run_definitions`], but definitions with a result in `results` already ran
+    /// and are not run again. The results of the definitions that run are added to `results`.
+    pub(crate) fn run_pending(
+        &self,
+        names: &[String],
+        test_mode: bool,
+        results: &mut Vec<DefinitionResult>,
+        mut on_result: impl FnMut(&DefinitionResult) -> Result<()>,
+    ) -> Result<()> {
+        let definitions = self.definitions();
+        // Depending on a parametrized definition means depending on all of its cases
+        let graph: Vec<(String, Vec<String>)> = definitions
+            .iter()
+            .map(|def| {
+                let depends_on = def
+                    .depends_on
+                    .iter()
+                    .flat_map(|dep| {
+                        let cases: Vec<String> = definitions
+                            .iter()
+                            .filter(|d| d.case.is_some() && d.base_name() == dep)
+                            .map(|d| d.name.clone())
+                            .collect();
+                        match cases.is_empty() {
+                            true => vec![dep.clone()],
+                            false => cases,
+                        }
+                    })
+                    .collect();
+                (def.name.clone(), depends_on)
+            })
+            .collect();
+        let order = resolve_order(&graph, names).context("Failed to resolve dependencies")?;
+
+        let mut aborted: Option<String> = results.iter().find_map(abort_reason);
+
+        for name in order {
+            if results.iter().any(|r| r.name == name) {
+                continue;
+            }
 
-        for def in definitions {
-            self.run_definition(def.clone(), true)
-                .context("Failed to run definition")
-                .context(format!("Running test for definition: {}", def))?;
+            let depends_on = graph
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, deps)| deps.as_slice())
+                .unwrap_or_default();
+            let skip = aborted
+                .clone()
+                .or_else(|| dependency_skip(depends_on, results));
+
+            let result = self
+                .execute_definition(name.clone(), test_mode, skip)
+                .context(format!("Failed to run definition: {}", name))?;
+            aborted = aborted.or_else(|| abort_reason(&result));
+            on_result(&result)?;
+            results.push(result);
         }
 
         Ok(())
     }
 
+    /// Takes the requests sent outside of definitions since they were last taken, by on_init,
+    /// on_cleanup and the teardown of session fixtures.
+    pub fn take_requests(&self) -> Vec<RequestRecord> {
+        let mut requests = std::mem::take(&mut *self.state.script_requests.lock().unwrap());
+        requests.append(&mut self.state.requests.lock().unwrap());
+
+        requests
+    }
+
+    /// Runs all definitions as tests and returns their results.
+    pub fn run_tests(&self) -> Result<RunReport> {
+        self.run_tests_with(|_| Ok(()))
+    }
+
+    /// Runs all definitions as tests, calling `on_result` as soon as each one finishes.
+    pub fn run_tests_with(
+        &self,
+        on_result: impl FnMut(&DefinitionResult) -> Result<()>,
+    ) -> Result<RunReport> {
+        self.run_definitions(&self.list_definitions(), true, on_result)
+    }
+
     pub fn list_definitions(&self) -> Vec<String> {
-        Python::with_gil(|py| {
-            crate::api::REGISTRY
-                .lock()
-                .unwrap()
-                .iter()
-                .map(|def| {
-                    def.as_ref()
-                        .getattr(py, "__name__")
-                        .unwrap()
-                        .extract::<String>(py)
-                        .unwrap()
-                })
-                .collect()
-        })
+        self.state
+            .registry
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|def| def.meta.name.clone())
+            .collect()
     }
 
-    /// Load libs from venv
+    /// Returns the metadata of all definitions, in declaration order.
+    pub fn definitions(&self) -> Vec<DefinitionMeta> {
+        self.state
+            .registry
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|def| def.meta.clone())
+            .collect()
+    }
+
+    /// Returns the names of the selected definitions, in declaration order.
+    ///
+    /// Selecting a parametrized definition by its name without a case id selects all cases.
+    pub fn select_definitions(&self, selection: &Selection) -> Vec<String> {
+        self.state
+            .registry
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|def| {
+                selection.matches(&def.meta.name, &def.meta.tags)
+                    || selection.matches(def.meta.base_name(), &def.meta.tags)
+            })
+            .map(|def| def.meta.name.clone())
+            .collect()
+    }
+
+    /// Executes the script source, registering its definitions and hooks
+    fn load_script(&mut self) -> Result<()> {
+        for i in 0..self.files.len() {
+            let module = self.with_python(|py| {
+                let file = &self.files[i];
+                *self.state.namespace.lock().unwrap() = file.namespace.clone();
+                *self.state.script_dir.lock().unwrap() = script_dir(&file.path).to_path_buf();
+                let module = create_module(py, file);
+                *self.state.namespace.lock().unwrap() = None;
+
+                let module = module.context(format!(
+                    "Failed to create module from {}",
+                    file.path.display()
+                ))?;
+                Ok(module.unbind())
+            })?;
+            self.files[i].module = Some(module);
+        }
+        *self.state.script_dir.lock().unwrap() = script_dir(&self.files[0].path).to_path_buf();
+
+        Ok(())
+    }
+
+    /// Load libs from the venv of the script, see [`crate::venv::find`]
     fn load_venv_libs(&self, py: Python<'_>) -> Result<()> {
         let sys = py.import("sys")?;
         let version: String = sys.getattr("version")?.extract()?;
         tracing::debug!("Python version: {}", version);
 
-        if std::env::var("VIRTUAL_ENV").is_ok() {
-            if let Ok(venv) = std::env::var("VIRTUAL_ENV") {
-                let site_packages = PathBuf::from(venv)
-                    .join("lib")
-                    .join("python3.11")
-                    .join("site-packages");
-                let site = py.import("site")?;
-                site.call_method1("addsitedir", (site_packages,))?;
-                return Ok(());
-            }
+        let version_info = sys.getattr("version_info")?;
+        let python = PythonVersion {
+            major: version_info.getattr("major")?.extract()?,
+            minor: version_info.getattr("minor")?.extract()?,
+        };
+
+        let script_dir = self
+            .files
+            .first()
+            .map_or(Path::new("."), |file| script_dir(&file.path));
+        if let Some(venv) = venv::find(script_dir, python)? {
+            tracing::debug!(
+                "Using virtual environment {}, loading libraries from {}",
+                venv.root.display(),
+                venv.site_packages.display()
+            );
+            let site = py.import("site")?;
+            site.call_method1("addsitedir", (venv.site_packages,))?;
+            return Ok(());
         }
 
         tracing::warn!(
@@ -191,58 +688,335 @@ impl Vm {
         Ok(())
     }
 
-    /// Adds the neocurl module to the Python interpreter
-    fn add_neocurl_module(&self, py: Python<'_>) -> Result<()> {
+    /// Creates the neocurl module of this VM and adds it to the Python interpreter
+    fn add_neocurl_module(&mut self, py: Python<'_>) -> Result<()> {
         let sys_modules = py.import("sys")?.getattr("modules")?;
         let module = PyModule::new(py, "neocurl")?;
         super::api::neocurl_py_module(&module)?;
-        sys_modules.set_item("neocurl", module)?;
+        crate::api::attach_state(&module, self.state.clone())?;
+        sys_modules.set_item("neocurl", &module)?;
+        self.neocurl = Some(module.unbind());
 
         Ok(())
     }
 
-    /// Adds the neocurl module to the Python interpreter
-    fn create_module_from_code<'a>(&self, py: Python<'a>) -> Result<Bound<'a, PyModule>> {
-        let code =
-            CString::new(self.source.clone()).expect("Failed to create CString from source code");
-        let module = PyModule::from_code(py, &code, c_str!("neocurl.py"), c_str!("main"))
-            .context("Failed to create module from code")?;
-
-        Ok(module)
-    }
-
-    /// Runs on_init function in the script
+    /// Runs the on_init functions in the script
     fn run_on_init(&self, py: Python<'_>) -> Result<()> {
-        let on_init = super::api::ON_INIT.lock().unwrap();
-        if let Some(func) = on_init.as_ref() {
-            func.call0(py).context("Failed to call on_init function")?;
+        let on_init: Vec<Py<PyAny>> = self
+            .state
+            .on_init
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|func| func.clone_ref(py))
+            .collect();
+
+        for func in on_init {
+            if let Err(e) = func.call0(py) {
+                crate::api::write_error(&self.state, &DefinitionError::from_pyerr(py, &e))?;
+                return Err(anyhow::Error::from(e).context("Failed to call on_init function"));
+            }
         }
 
         Ok(())
     }
 
-    /// Runs on_cleanup function in the script
+    /// Runs all on_cleanup functions in the script, even if one of them fails
     fn run_on_cleanup(&self, py: Python<'_>) -> Result<()> {
-        let on_cleanup = super::api::ON_CLEANUP.lock().unwrap();
-        if let Some(func) = on_cleanup.as_ref() {
-            func.call0(py)
-                .context("Failed to call on_cleanup function")?;
+        let on_cleanup: Vec<Py<PyAny>> = self
+            .state
+            .on_cleanup
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|func| func.clone_ref(py))
+            .collect();
+
+        let mut result = Ok(());
+        for func in on_cleanup {
+            if let Err(e) = func.call0(py) {
+                crate::api::write_error(&self.state, &DefinitionError::from_pyerr(py, &e))?;
+                if result.is_ok() {
+                    result =
+                        Err(anyhow::Error::from(e).context("Failed to call on_cleanup function"));
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Orders `names` and everything they depend on so that dependencies come first.
+///
+/// `graph` maps every definition to its dependencies, in declaration order. Independent
+/// definitions keep that order.
+fn resolve_order(graph: &[(String, Vec<String>)], names: &[String]) -> Result<Vec<String>> {
+    fn visit(
+        name: &str,
+        graph: &[(String, Vec<String>)],
+        path: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if order.iter().any(|n| n == name) {
+            return Ok(());
+        }
+
+        if let Some(i) = path.iter().position(|n| n == name) {
+            let cycle = path[i..].join(" -> ");
+            return Err(anyhow::anyhow!("Dependency cycle: {} -> {}", cycle, name));
+        }
+
+        let Some((_, depends_on)) = graph.iter().find(|(n, _)| n == name) else {
+            return Err(match path.last() {
+                Some(parent) => anyhow::anyhow!(
+                    "Definition `{}` depends on unknown definition `{}`",
+                    parent,
+                    name
+                ),
+                None => anyhow::anyhow!("Definition not found: {}", name),
+            });
+        };
+
+        path.push(name.to_string());
+        for dep in depends_on {
+            visit(dep, graph, path, order)?;
         }
+        path.pop();
+
+        order.push(name.to_string());
 
         Ok(())
     }
+
+    let mut order = Vec::new();
+    for name in names {
+        visit(name, graph, &mut Vec::new(), &mut order)?;
+    }
+
+    Ok(order)
 }
 
-/// Reads the file specified in the arguments
-fn read_file(file: String) -> Result<String> {
-    let file_path = std::path::Path::new(&file);
+/// Why a definition is skipped because of its dependencies: the first one that did not pass.
+fn dependency_skip(depends_on: &[String], results: &[DefinitionResult]) -> Option<String> {
+    depends_on.iter().find_map(|dep| {
+        let dep_result = results.iter().find(|r| r.name == *dep)?;
+        match (dep_result.passed, &dep_result.skipped) {
+            (true, _) => None,
+            (false, Some(_)) => Some(format!("dependency `{}` was skipped", dep)),
+            (false, None) => Some(format!("dependency `{}` failed", dep)),
+        }
+    })
+}
+
+/// Why the remaining definitions are skipped after a definition called `nc.fatal`.
+fn abort_reason(result: &DefinitionResult) -> Option<String> {
+    let error = result.error.as_ref().filter(|e| e.is_fatal())?;
 
+    Some(format!(
+        "run aborted by `{}`: {}",
+        result.name, error.message
+    ))
+}
+
+/// Reads the file specified in the arguments
+fn read_file(file_path: &Path) -> Result<String> {
     if !file_path.exists() {
-        return Err(anyhow::anyhow!("File does not exist: {}", file));
+        return Err(anyhow::anyhow!(
+            "File does not exist: {}",
+            file_path.display()
+        ));
     }
 
-    let file_contents =
-        std::fs::read_to_string(file_path).context(format!("Failed to read file: {}", file))?;
+    let file_contents = std::fs::read_to_string(file_path)
+        .context(format!("Failed to read file: {}", file_path.display()))?;
 
     Ok(file_contents)
 }
+
+/// Files loaded for a `--file` argument: the file itself, the `.py` files of a directory, or
+/// the files matching a glob. Sorted by path.
+fn script_paths(source: &Path) -> Result<Vec<PathBuf>> {
+    if source.is_file() {
+        return Ok(vec![source.to_path_buf()]);
+    }
+
+    let mut paths: Vec<PathBuf> = if source.is_dir() {
+        std::fs::read_dir(source)
+            .context(format!("Failed to read directory: {}", source.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.is_file()
+                    && path.extension().is_some_and(|ext| ext == "py")
+                    && !file_stem(path).starts_with('_')
+            })
+            .collect()
+    } else {
+        let pattern = source.to_string_lossy();
+        if !pattern.contains(['*', '?', '[']) {
+            return Err(anyhow::anyhow!("File does not exist: {}", source.display()));
+        }
+
+        glob::glob(&pattern)
+            .context(format!("Invalid glob: {}", pattern))?
+            .filter_map(|path| path.ok())
+            .filter(|path| path.is_file())
+            .collect()
+    };
+    paths.sort();
+
+    if paths.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No script files found in {}",
+            source.display()
+        ));
+    }
+
+    Ok(paths)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().to_string())
+}
+
+/// Directory of a script, `.` for a relative path without one.
+pub(crate) fn script_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Imports a script file as a module.
+///
+/// The directory of the script is added to `sys.path` so it can import files next to it. The
+/// module is named after the file, like an import of it would be, unless another module already
+/// has that name.
+fn create_module<'py>(py: Python<'py>, file: &ScriptFile) -> Result<Bound<'py, PyModule>> {
+    let sys = py.import("sys")?;
+
+    let dir = script_dir(&file.path);
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    // sys.path only takes strings, paths would be converted to pathlib objects
+    let dir = dir.to_string_lossy().to_string();
+    let sys_path = sys.getattr("path")?;
+    if !sys_path.contains(&dir)? {
+        sys_path.call_method1("insert", (0, &dir))?;
+    }
+
+    let path = file.path.to_string_lossy().to_string();
+    let mut name = file_stem(&file.path);
+    if let Ok(existing) = sys.getattr("modules")?.get_item(&name) {
+        let existing_file = existing
+            .getattr("__file__")
+            .ok()
+            .and_then(|f| f.extract::<String>().ok());
+        if existing_file.as_deref() != Some(path.as_str()) {
+            name = format!("ncurl_{}", name);
+        }
+    }
+
+    let code = CString::new(file.source.clone()).context("Script contains a null byte")?;
+    let file_name = CString::new(path).context("Script path contains a null byte")?;
+    let name = CString::new(name).context("Script name contains a null byte")?;
+    let module = PyModule::from_code(py, &code, &file_name, &name)
+        .context("Failed to create module from code")?;
+
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Assertions;
+
+    fn graph(edges: &[(&str, &[&str])]) -> Vec<(String, Vec<String>)> {
+        edges
+            .iter()
+            .map(|(name, deps)| (name.to_string(), strings(deps)))
+            .collect()
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn result(name: &str, passed: bool, skipped: Option<&str>) -> DefinitionResult {
+        DefinitionResult {
+            name: name.to_string(),
+            tags: Vec::new(),
+            description: None,
+            passed,
+            skipped: skipped.map(str::to_string),
+            duration_ms: 0,
+            error: None,
+            assertions: Assertions::default(),
+            requests: Vec::new(),
+            logs: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let graph = graph(&[
+            ("login", &[]),
+            ("create", &["login"]),
+            ("get", &["create", "login"]),
+            ("health", &[]),
+        ]);
+
+        let order = resolve_order(&graph, &strings(&["get", "health"])).unwrap();
+        assert_eq!(order, strings(&["login", "create", "get", "health"]));
+
+        // Each definition is ordered once, also when several depend on it
+        let order = resolve_order(&graph, &strings(&["create", "get", "login"])).unwrap();
+        assert_eq!(order, strings(&["login", "create", "get"]));
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let graph = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["a"]), ("d", &["a"])]);
+
+        let error = resolve_order(&graph, &strings(&["d"])).unwrap_err();
+        assert_eq!(error.to_string(), "Dependency cycle: a -> b -> c -> a");
+
+        let graph = self::graph(&[("a", &["a"])]);
+        assert!(resolve_order(&graph, &strings(&["a"])).is_err());
+    }
+
+    #[test]
+    fn unknown_definitions_are_errors() {
+        let graph = graph(&[("a", &["missing"])]);
+
+        let error = resolve_order(&graph, &strings(&["a"])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Definition `a` depends on unknown definition `missing`"
+        );
+
+        let error = resolve_order(&graph, &strings(&["b"])).unwrap_err();
+        assert_eq!(error.to_string(), "Definition not found: b");
+    }
+
+    #[test]
+    fn dependents_are_skipped() {
+        let results = [
+            result("login", true, None),
+            result("create", false, None),
+            result("slow", false, Some("")),
+        ];
+
+        assert_eq!(dependency_skip(&strings(&["login"]), &results), None);
+        assert_eq!(
+            dependency_skip(&strings(&["login", "create"]), &results),
+            Some("dependency `create` failed".to_string())
+        );
+        assert_eq!(
+            dependency_skip(&strings(&["slow", "create"]), &results),
+            Some("dependency `slow` was skipped".to_string())
+        );
+        // Dependencies without a result have not run yet
+        assert_eq!(dependency_skip(&strings(&["other"]), &results), None);
+    }
+}
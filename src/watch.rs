@@ -0,0 +1,259 @@
+//! Watch mode, re-running definitions when the script changes.
+
+use crate::{
+    DefinitionResult, LogOutput, RunReport,
+    select::SelectArgs,
+    vm::{Vm, script_dir},
+};
+use anyhow::{Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use owo_colors::{OwoColorize, XtermColors};
+use pyo3::{prelude::*, types::PyDict};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+/// How long the files have to stay unchanged before the definitions run again, editors often
+/// write a file in several steps.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Runs the selected definitions, or all of them as tests with `all`, every time a script file
+/// or a module it imports from next to it changes. Runs until interrupted.
+///
+/// Every run loads the script into a new VM, so `on_init` and `on_cleanup` run each time.
+pub fn watch(file: &str, select: &SelectArgs, all: bool) -> Result<()> {
+    if select.is_empty() && !all {
+        return Err(anyhow::anyhow!(
+            "No definitions selected, pass a name, pattern, --tag or --all"
+        ));
+    }
+    let selection = select.selection()?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to watch files")?;
+    let mut watched_dirs: HashSet<PathBuf> = HashSet::new();
+    let mut files: HashSet<PathBuf> = HashSet::new();
+    let mut previous: Option<RunReport> = None;
+
+    loop {
+        let mut vm = match Vm::builder()
+            .load(file)
+            .and_then(|builder| builder.output(LogOutput::Stdout).build())
+        {
+            Ok(vm) => vm,
+            // Without a VM there is nothing to watch yet
+            Err(e) if files.is_empty() => return Err(e.context("Failed to load source to VM")),
+            Err(e) => {
+                eprintln!("{}", format!("{:#}", e).red());
+                wait_for_change(&rx, &files)?;
+                continue;
+            }
+        };
+
+        let result = vm.init().context("Failed to run VM").and_then(|_| {
+            if all {
+                return vm.run_tests();
+            }
+
+            let names = vm.select_definitions(&selection);
+            if names.is_empty() {
+                return Err(anyhow::anyhow!("No definitions match the selection"));
+            }
+            vm.run_definitions(&names, false, |_| Ok(()))
+        });
+        let cleanup = vm.cleanup().context("Failed to cleanup VM");
+
+        let dirs: Vec<PathBuf> = vm
+            .paths()
+            .iter()
+            .map(|path| canonical(script_dir(path)))
+            .collect();
+        files = forget_modules(&dirs)?;
+        files.extend(vm.paths().into_iter().map(canonical));
+        drop(vm);
+
+        match result.and_then(|report| cleanup.map(|_| report)) {
+            Ok(report) => {
+                print_diff(previous.as_ref(), &report);
+                previous = Some(report);
+            }
+            Err(e) => eprintln!("{}", format!("{:#}", e).red()),
+        }
+
+        // Directories of scripts are watched for new scripts, the others only for their files
+        let parents = files.iter().filter_map(|file| file.parent());
+        for dir in dirs.iter().map(|dir| dir.as_path()).chain(parents) {
+            if watched_dirs.insert(dir.to_path_buf()) {
+                watcher
+                    .watch(dir, RecursiveMode::NonRecursive)
+                    .context(format!("Failed to watch {}", dir.display()))?;
+            }
+        }
+        files.extend(dirs);
+
+        println!(
+            "{}",
+            "Watching for changes, press Ctrl-C to stop".color(XtermColors::DarkGray)
+        );
+        wait_for_change(&rx, &files)?;
+    }
+}
+
+/// Blocks until a watched file changes or a script is added to a watched script directory,
+/// then until the files stop changing.
+fn wait_for_change(
+    rx: &mpsc::Receiver<notify::Result<notify::Event>>,
+    files: &HashSet<PathBuf>,
+) -> Result<()> {
+    let is_change = |event: notify::Result<notify::Event>| match event {
+        Ok(event) => {
+            matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            ) && event.paths.iter().any(|path| {
+                files.contains(path)
+                    || path.extension().is_some_and(|ext| ext == "py")
+                        && path.parent().is_some_and(|dir| files.contains(dir))
+            })
+        }
+        Err(e) => {
+            tracing::warn!("Failed to watch files: {}", e);
+            false
+        }
+    };
+
+    loop {
+        let event = rx.recv().context("File watcher stopped")?;
+        if is_change(event) {
+            break;
+        }
+    }
+    while rx.recv_timeout(DEBOUNCE).is_ok() {}
+
+    Ok(())
+}
+
+/// Removes modules imported from the script directories from `sys.modules`, so the next run
+/// imports their new version, and returns their files. Installed packages are kept.
+fn forget_modules(dirs: &[PathBuf]) -> Result<HashSet<PathBuf>> {
+    Python::with_gil(|py| {
+        let modules = py.import("sys")?.getattr("modules")?;
+        let modules = modules.downcast::<PyDict>().map_err(PyErr::from)?;
+
+        let mut files = HashSet::new();
+        for (name, module) in modules
+            .items()
+            .extract::<Vec<(String, Bound<'_, PyAny>)>>()?
+        {
+            let Some(file) = module
+                .getattr("__file__")
+                .ok()
+                .and_then(|file| file.extract::<String>().ok())
+            else {
+                continue;
+            };
+
+            let file = canonical(Path::new(&file));
+            let is_local = dirs.iter().any(|dir| file.starts_with(dir))
+                && !file.components().any(|c| c.as_os_str() == "site-packages");
+            if is_local {
+                modules.del_item(name)?;
+                files.insert(file);
+            }
+        }
+
+        Ok(files)
+    })
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Prints the counts of the run and the definitions whose outcome changed since the previous
+/// one. On the first run, the failed definitions are listed instead.
+fn print_diff(previous: Option<&RunReport>, report: &RunReport) {
+    println!(
+        "{} {} passed, {} failed, {} skipped in {}ms",
+        chrono::Local::now()
+            .format("%H:%M:%S")
+            .color(XtermColors::DarkGray),
+        report.passed.green(),
+        report.failed.red(),
+        report.skipped.yellow(),
+        report.duration_ms
+    );
+
+    let Some(previous) = previous else {
+        for result in report.definitions.iter().filter(|r| outcome(r) == "failed") {
+            println!("  {} {}{}", "failed".red(), result.name, reason(result));
+        }
+        return;
+    };
+
+    let mut changed = false;
+    for result in &report.definitions {
+        let before = previous
+            .definitions
+            .iter()
+            .find(|r| r.name == result.name)
+            .map(outcome);
+        let after = outcome(result);
+        if before == Some(after) {
+            continue;
+        }
+
+        changed = true;
+        let change = match before {
+            Some(before) => format!("{} -> {}", before, after),
+            None => format!("new, {}", after),
+        };
+        let change = match after {
+            "passed" => change.green().to_string(),
+            "failed" => change.red().to_string(),
+            _ => change.yellow().to_string(),
+        };
+        println!("  {} {}{}", change, result.name, reason(result));
+    }
+
+    for result in &previous.definitions {
+        if !report.definitions.iter().any(|r| r.name == result.name) {
+            changed = true;
+            println!(
+                "  {} {}",
+                "removed".color(XtermColors::DarkGray),
+                result.name
+            );
+        }
+    }
+
+    if !changed {
+        println!(
+            "  {}",
+            "No changes since the last run".color(XtermColors::DarkGray)
+        );
+    }
+}
+
+fn outcome(result: &DefinitionResult) -> &'static str {
+    match (result.passed, &result.skipped) {
+        (true, _) => "passed",
+        (false, Some(_)) => "skipped",
+        (false, None) => "failed",
+    }
+}
+
+/// The error of a failed definition, to show next to its name.
+fn reason(result: &DefinitionResult) -> String {
+    match &result.error {
+        Some(error) if !result.passed => format!(": {}", error)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,31 @@
+//! Runs a script through the libtest-mimic harness, as `cargo test --test harness`.
+
+use neocurl::testing::Script;
+use std::{path::Path, process::ExitCode};
+
+fn main() -> ExitCode {
+    let events = std::env::temp_dir().join(format!("neocurl-harness-{}.txt", std::process::id()));
+    let _ = std::fs::remove_file(&events);
+
+    let code = Script::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts/harness.py"))
+        .env("EVENTS", events.to_str().unwrap())
+        .harness();
+
+    let events = std::fs::read_to_string(&events)
+        .inspect(|_| std::fs::remove_file(&events).unwrap())
+        .unwrap_or_default();
+    let count = |name: &str| events.lines().filter(|line| *line == name).count();
+
+    // Listing tests starts nothing, running any starts and cleans up the script once
+    assert!(
+        count("on_init") <= 1,
+        "on_init ran more than once:\n{}",
+        events
+    );
+    assert_eq!(count("on_init"), count("on_cleanup"), "{}", events);
+    // Definitions run at most once, however many tests depend on them
+    assert!(count("login") <= 1, "login ran more than once:\n{}", events);
+    assert_eq!(count("update_user"), 0, "{}", events);
+
+    code
+}
@@ -0,0 +1,187 @@
+use neocurl::testing::Script;
+use std::path::{Path, PathBuf};
+
+fn script(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/scripts")
+        .join(name)
+}
+
+/// A file the script appends the events it went through to, removed when dropped.
+struct Events(PathBuf);
+
+impl Events {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "neocurl-events-{}-{}.txt",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        Events(path)
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    fn read(&self) -> Vec<String> {
+        std::fs::read_to_string(&self.0)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Drop for Events {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn report() {
+    let events = Events::new("report");
+    let report = Script::new(script("events.py"))
+        .env("EVENTS", events.path())
+        .report()
+        .unwrap();
+
+    assert_eq!((report.passed, report.failed, report.skipped), (1, 1, 2));
+    let outcomes: Vec<(&str, bool, Option<&str>)> = report
+        .definitions
+        .iter()
+        .map(|d| (d.name.as_str(), d.passed, d.skipped.as_deref()))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            ("first", true, None),
+            ("second", false, None),
+            ("third", false, Some("dependency `second` failed")),
+            ("fourth", false, Some("not ready")),
+        ]
+    );
+
+    let first = &report.definitions[0];
+    assert_eq!((first.assertions.passed, first.assertions.failed), (1, 0));
+    let error = report.definitions[1].error.as_ref().unwrap();
+    assert_eq!(error.kind, "AssertionError");
+    assert_eq!(error.message, "expected another item");
+    assert_eq!(error.location().unwrap().function, "second");
+}
+
+#[test]
+fn fixture_order() {
+    let events = Events::new("fixtures");
+    Script::new(script("events.py"))
+        .env("EVENTS", events.path())
+        .report()
+        .unwrap();
+
+    assert_eq!(
+        events.read(),
+        [
+            "on_init",
+            // Hooks only set up the fixtures they request
+            "before_each",
+            "session setup",
+            "item setup",
+            "first with session item",
+            "after_each",
+            "item teardown",
+            "before_each",
+            "item setup",
+            "second",
+            "after_each",
+            "item teardown",
+            "session teardown",
+            "on_cleanup",
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "1 of 4 definitions failed")]
+fn run_panics_on_failures() {
+    let events = Events::new("run");
+    Script::new(script("events.py"))
+        .env("EVENTS", events.path())
+        .run();
+}
+
+#[test]
+fn run_definition_with_dependencies() {
+    let events = Events::new("definition");
+    let report = Script::new(script("events.py"))
+        .env("EVENTS", events.path())
+        .run_definition("first");
+
+    assert_eq!(report.definitions.len(), 1);
+    assert!(!events.read().contains(&"second".to_string()));
+}
+
+#[test]
+fn cleanup_after_fatal() {
+    let events = Events::new("fatal");
+    let report = Script::new(script("fatal.py"))
+        .env("EVENTS", events.path())
+        .report()
+        .unwrap();
+
+    assert_eq!((report.passed, report.failed, report.skipped), (0, 1, 1));
+    assert_eq!(
+        report.definitions[0].error.as_ref().unwrap().kind,
+        "FatalError"
+    );
+    assert_eq!(
+        report.definitions[1].skipped.as_deref(),
+        Some("run aborted by `first`: stop")
+    );
+    assert_eq!(
+        events.read(),
+        [
+            "on_init",
+            "session setup",
+            "first",
+            "session teardown",
+            "on_cleanup"
+        ]
+    );
+}
+
+#[test]
+fn cleanup_after_fatal_init() {
+    let events = Events::new("fatal-init");
+    let error = Script::new(script("fatal.py"))
+        .env("EVENTS", events.path())
+        .env("FATAL_INIT", "1")
+        .report()
+        .unwrap_err();
+
+    assert!(format!("{:#}", error).contains("cannot start"));
+    assert_eq!(events.read(), ["on_init", "on_cleanup"]);
+}
+
+#[test]
+fn timeout_after_completion() {
+    let events = Events::new("timeout");
+    let report = Script::new(script("timeout.py"))
+        .env("EVENTS", events.path())
+        .report()
+        .unwrap();
+
+    assert_eq!((report.passed, report.failed), (1, 1));
+    let slow = &report.definitions[0];
+    let error = slow.error.as_ref().unwrap();
+    assert_eq!(error.kind, "TimeoutError");
+    assert!(
+        error
+            .message
+            .starts_with("Definition exceeded its timeout of 50ms")
+    );
+    // The definition is not interrupted, it runs to its end and is failed afterwards
+    assert!(slow.duration_ms >= 200);
+    assert_eq!(events.read(), ["slow finished", "fast finished"]);
+}
@@ -0,0 +1,62 @@
+import neocurl as nc
+
+
+def event(name):
+    with open(nc.env("EVENTS"), "a") as events:
+        events.write(name + "\n")
+
+
+@nc.on_init
+def init():
+    event("on_init")
+
+
+@nc.on_cleanup
+def cleanup():
+    event("on_cleanup")
+
+
+@nc.fixture(scope="session")
+def session():
+    event("session setup")
+    yield "session"
+    event("session teardown")
+
+
+@nc.fixture
+def item(session):
+    event("item setup")
+    yield session + " item"
+    event("item teardown")
+
+
+@nc.before_each
+def before():
+    event("before_each")
+
+
+@nc.after_each
+def after():
+    event("after_each")
+
+
+@nc.define
+def first(client, item):
+    event("first with " + item)
+    nc.assert_t(True)
+
+
+@nc.define
+def second(client, item):
+    event("second")
+    assert item == "other", "expected another item"
+
+
+@nc.define(depends_on=["second"])
+def third(client):
+    event("third")
+
+
+@nc.define(skip="not ready")
+def fourth(client):
+    event("fourth")
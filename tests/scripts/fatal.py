@@ -0,0 +1,36 @@
+import neocurl as nc
+
+
+def event(name):
+    with open(nc.env("EVENTS"), "a") as events:
+        events.write(name + "\n")
+
+
+@nc.on_init
+def init():
+    event("on_init")
+    if nc.env("FATAL_INIT"):
+        nc.fatal("cannot start")
+
+
+@nc.on_cleanup
+def cleanup():
+    event("on_cleanup")
+
+
+@nc.fixture(scope="session")
+def session():
+    event("session setup")
+    yield
+    event("session teardown")
+
+
+@nc.define
+def first(client, session):
+    event("first")
+    nc.fatal("stop")
+
+
+@nc.define
+def second(client):
+    event("second")
@@ -0,0 +1,36 @@
+import neocurl as nc
+
+
+def event(name):
+    with open(nc.env("EVENTS"), "a") as events:
+        events.write(name + "\n")
+
+
+@nc.on_init
+def init():
+    event("on_init")
+
+
+@nc.on_cleanup
+def cleanup():
+    event("on_cleanup")
+
+
+@nc.define
+def login(client):
+    event("login")
+
+
+@nc.define(depends_on=["login"])
+def create_user(client):
+    event("create_user")
+
+
+@nc.define(depends_on=["login", "create_user"])
+def delete_user(client):
+    event("delete_user")
+
+
+@nc.define(skip="not ready")
+def update_user(client):
+    event("update_user")
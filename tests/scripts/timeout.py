@@ -0,0 +1,19 @@
+import time
+
+import neocurl as nc
+
+
+def event(name):
+    with open(nc.env("EVENTS"), "a") as events:
+        events.write(name + "\n")
+
+
+@nc.define(timeout=50)
+def slow(client):
+    time.sleep(0.2)
+    event("slow finished")
+
+
+@nc.define(timeout=5_000)
+def fast(client):
+    event("fast finished")